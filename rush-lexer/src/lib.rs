@@ -1,8 +1,7 @@
 use std::iter::Peekable;
 use std::str::CharIndices;
 
-use crate::token::Token;
-pub use crate::token::{BytePos, Span, TokenKind, TokenStream};
+pub use crate::token::{BytePos, Span, Token, TokenKind, TokenStream};
 
 mod token;
 
@@ -10,6 +9,9 @@ mod token;
 pub struct Lexer<'src> {
     source: &'src str,
     chars: Peekable<CharIndices<'src>>,
+    line: usize,
+    /// An assignment name was just lexed, so the next token is its value, even when empty.
+    in_assignment: bool,
 }
 
 impl<'src> Lexer<'src> {
@@ -17,13 +19,59 @@ impl<'src> Lexer<'src> {
         Self {
             source,
             chars: source.char_indices().peekable(),
+            line: 1,
+            in_assignment: false,
         }
     }
 
+    /// 1-based line of the next character to be lexed.
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
     pub fn lex(&mut self) -> TokenStream {
-        let mut tokens = vec![];
-        if self.source.is_empty() {
-            return TokenStream::new(tokens, self.source.len());
+        let eof = self.source.len();
+        TokenStream::new(self.tokens(), eof)
+    }
+
+    /// The token at byte offset `pos` of `source`, or `None` when `pos` falls in whitespace, a
+    /// comment or outside of the source. Only the line holding `pos` is lexed, so a quoted string
+    /// opened on an earlier line isn't seen.
+    pub fn token_at(source: &'src str, pos: usize) -> Option<Token> {
+        let line_start = source.get(..pos)?.rfind('\n').map_or(0, |idx| idx + 1);
+
+        let mut lexer = Lexer::new(source);
+        while lexer.peek().is_some_and(|(byte_pos, _)| byte_pos < line_start) {
+            lexer.next();
+        }
+
+        lexer
+            .tokens()
+            .take_while(|token| token.kind() != TokenKind::Eof && *token.span().start <= pos)
+            .find(|token| {
+                // single character operators have empty spans at their position
+                let span = token.span();
+                if span.is_empty() { *span.start == pos } else { pos < *span.end }
+            })
+    }
+
+    /// Lazily lexes the source one token at a time, ending with `Eof`. Nothing past the last
+    /// token taken is scanned.
+    pub fn tokens(&mut self) -> impl Iterator<Item = Token> + '_ {
+        let mut finished = false;
+        std::iter::from_fn(move || {
+            if finished {
+                return None;
+            }
+            let token = self.next_token();
+            finished = token.kind() == TokenKind::Eof;
+            Some(token)
+        })
+    }
+
+    fn next_token(&mut self) -> Token {
+        if std::mem::take(&mut self.in_assignment) {
+            return self.take_assignment_value();
         }
 
         while let Some((byte_pos, curr)) = self.next() {
@@ -33,31 +81,225 @@ impl<'src> Lexer<'src> {
 
             let next = self.peek();
 
-            match (curr, next) {
-                ('|', _) => tokens.push(TokenKind::Pipe.into_token(byte_pos)),
-                (';', _) => tokens.push(TokenKind::Semi.into_token(byte_pos)),
-                ('&', _) => tokens.push(TokenKind::Ampersand.into_token(byte_pos)),
-                _ => tokens.push(self.take_atom(byte_pos)),
-            }
+            let token = match (curr, next) {
+                // a `#` starting a token comments out the rest of the line, inside a word or quotes
+                // it is taken by `take_atom` or `take_quoted` instead
+                ('#', _) => {
+                    self.take_while(|c| c != '\n', byte_pos);
+                    continue;
+                }
+                ('&', Some((_, '&'))) => {
+                    self.next();
+                    TokenKind::AndIf.into_token((byte_pos, byte_pos + 2))
+                }
+                ('|', Some((_, '|'))) => {
+                    self.next();
+                    TokenKind::OrIf.into_token((byte_pos, byte_pos + 2))
+                }
+                ('>', Some((_, '>'))) => {
+                    self.next();
+                    TokenKind::DGreat.into_token((byte_pos, byte_pos + 2))
+                }
+                ('>', Some((_, '&'))) => {
+                    self.next();
+                    TokenKind::GreatAnd.into_token((byte_pos, byte_pos + 2))
+                }
+                ('>', _) => TokenKind::Great.into_token((byte_pos, byte_pos + 1)),
+                ('<', _) => TokenKind::Less.into_token((byte_pos, byte_pos + 1)),
+                ('!', None) => TokenKind::Bang.into_token((byte_pos, byte_pos + 1)),
+                ('!', Some((_, next))) if is_delimiter(next) => TokenKind::Bang.into_token((byte_pos, byte_pos + 1)),
+                ('(', _) => TokenKind::LParen.into_token((byte_pos, byte_pos + 1)),
+                (')', _) => TokenKind::RParen.into_token((byte_pos, byte_pos + 1)),
+                ('|', _) => TokenKind::Pipe.into_token(byte_pos),
+                (';', _) => TokenKind::Semi.into_token(byte_pos),
+                ('&', _) => TokenKind::Ampersand.into_token(byte_pos),
+                ('\'' | '"', _) => self.take_quoted(byte_pos, curr),
+                ('$', _) => match self.take_variable(byte_pos) {
+                    Some(token) => token,
+                    None => self.take_atom(byte_pos),
+                },
+                _ => match self.take_assignment_name(byte_pos) {
+                    Some(token) => token,
+                    None => self.take_atom(byte_pos),
+                },
+            };
+
+            return token;
         }
 
-        tokens.push(self.eof());
-        TokenStream::new(tokens, self.source.len())
+        self.eof()
     }
 
+    /// A word up to the next delimiter, except that a `$(...)` in it runs up to its closing
+    /// parenthesis, whatever it holds, and a quoted part in it, as in `foo"bar baz"`, up to its
+    /// closing quote.
     fn take_atom(&mut self, start: usize) -> Token {
-        let end = self.take_while(|c| !is_delimiter(c), start);
-        TokenKind::Atom.into_token((start, end))
+        let mut end = start + self.source[start..].chars().next().map_or(0, char::len_utf8);
+        if self.source[start..].starts_with('\\') {
+            self.take_escaped_quote(&mut end);
+        }
+        self.take_word_rest(start, end)
+    }
+
+    /// The rest of a word whose text so far spans `start..end`, see [`Lexer::take_atom`]. A quoted
+    /// part left open makes the whole word an `UnterminatedQuote`.
+    fn take_word_rest(&mut self, start: usize, mut end: usize) -> Token {
+        loop {
+            if self.source[..end].ends_with('$') && self.peek_char() == Some('(') {
+                let group = &self.source[end..];
+                end += command_substitution_len(group).unwrap_or(group.len());
+                while self.peek().is_some_and(|(byte_pos, _)| byte_pos < end) {
+                    self.next();
+                }
+                continue;
+            }
+
+            match self.peek() {
+                Some((_, quote @ ('\'' | '"'))) => {
+                    self.next();
+                    match self.skip_quoted(quote) {
+                        Some(closing) => end = closing + quote.len_utf8(),
+                        None => return TokenKind::UnterminatedQuote.into_token((start, self.source.len())),
+                    }
+                }
+                Some((byte_pos, '\\')) => {
+                    self.next();
+                    end = byte_pos + 1;
+                    self.take_escaped_quote(&mut end);
+                }
+                Some((byte_pos, ch)) if !is_delimiter(ch) => {
+                    self.next();
+                    end = byte_pos + ch.len_utf8();
+                }
+                _ => break,
+            }
+        }
+
+        // digits right before a redirection operator name the fd it applies to, as in `2>err`
+        let is_io_number =
+            self.source[start..end].bytes().all(|b| b.is_ascii_digit()) && matches!(self.peek(), Some((_, '>' | '<')));
+
+        let kind = if is_io_number { TokenKind::IoNumber } else { TokenKind::Atom };
+        kind.into_token((start, end))
+    }
+
+    /// After a backslash in a word, takes the quote it escapes, which is then part of the word
+    /// instead of opening a quoted part.
+    fn take_escaped_quote(&mut self, end: &mut usize) {
+        if let Some((byte_pos, '\'' | '"')) = self.peek() {
+            self.next();
+            *end = byte_pos + 1;
+        }
+    }
+
+    /// Everything up to the closing quote is part of the quoted atom, which spans the text between the
+    /// quotes. Inside double quotes a backslash escapes the next character, so `\"` doesn't close
+    /// the string. A missing closing quote yields an `UnterminatedQuote` spanning the rest of the source.
+    /// When the word goes on past the closing quote, as in `"foo"bar`, it is an atom like any word
+    /// with a quoted part.
+    fn take_quoted(&mut self, opening: usize, quote: char) -> Token {
+        let start = opening + quote.len_utf8();
+        let Some(closing) = self.skip_quoted(quote) else {
+            return TokenKind::UnterminatedQuote.into_token((start, self.source.len()));
+        };
+
+        if self.peek().is_some_and(|(_, ch)| !is_delimiter(ch)) {
+            return self.take_word_rest(opening, closing + quote.len_utf8());
+        }
+        TokenKind::QuotedAtom.into_token((start, closing))
+    }
+
+    /// Consumes a quoted string, whose opening quote was already consumed, up to its closing
+    /// quote, returning where the closing quote is. `None` when it isn't closed.
+    fn skip_quoted(&mut self, quote: char) -> Option<usize> {
+        while let Some((byte_pos, ch)) = self.next() {
+            match ch {
+                '\\' if quote == '"' => _ = self.next(),
+                ch if ch == quote => return Some(byte_pos),
+                _ => {}
+            }
+        }
+        None
+    }
+
+    /// A word that is exactly `$NAME` or `${NAME}` is a variable spanning just the name. When the
+    /// `$` isn't followed by a valid name, or the word goes on past it, `None` is returned and the
+    /// word is left to `take_atom` as literal text.
+    fn take_variable(&mut self, dollar: usize) -> Option<Token> {
+        let braced = self.source[dollar + 1..].starts_with('{');
+        let start = if braced { dollar + 2 } else { dollar + 1 };
+        let name = &self.source[start..];
+        let end = match identifier_len(name) {
+            // special parameters are named by a single character, as in `$?`
+            0 if name.starts_with(['?', '$', '!']) => start + 1,
+            0 => return None,
+            len => start + len,
+        };
+
+        let word_end = if braced { self.source[end..].starts_with('}').then_some(end + 1)? } else { end };
+        if self.source[word_end..].chars().next().is_some_and(|c| !is_delimiter(c)) {
+            return None;
+        }
+
+        while self.peek().is_some_and(|(byte_pos, _)| byte_pos < word_end) {
+            self.next();
+        }
+
+        Some(TokenKind::Variable.into_token((start, end)))
+    }
+
+    /// A word starting with `NAME=` is an assignment, lexed as the name followed by a token for
+    /// the value. `None` is returned, consuming nothing, for any other word.
+    fn take_assignment_name(&mut self, start: usize) -> Option<Token> {
+        let end = start + identifier_len(&self.source[start..]);
+        if end == start || !self.source[end..].starts_with('=') {
+            return None;
+        }
+
+        // the name's first character was already consumed, the `=` goes with the name
+        while self.peek().is_some_and(|(byte_pos, _)| byte_pos <= end) {
+            self.next();
+        }
+
+        self.in_assignment = true;
+        Some(TokenKind::Assignment.into_token((start, end)))
+    }
+
+    /// The value right after `NAME=`, which is an empty atom when nothing follows the `=`.
+    fn take_assignment_value(&mut self) -> Token {
+        match self.peek() {
+            Some((byte_pos, quote @ ('\'' | '"'))) => {
+                self.next();
+                self.take_quoted(byte_pos, quote)
+            }
+            Some((byte_pos, ch)) if !is_delimiter(ch) => {
+                self.next();
+                match ch {
+                    '$' => self.take_variable(byte_pos).unwrap_or_else(|| self.take_atom(byte_pos)),
+                    _ => self.take_atom(byte_pos),
+                }
+            }
+            Some((byte_pos, _)) => TokenKind::Atom.into_token(byte_pos),
+            None => TokenKind::Atom.into_token(self.source.len()),
+        }
     }
 
     fn next(&mut self) -> Option<(usize, char)> {
-        self.chars.next()
+        let next = self.chars.next();
+        if let Some((_, '\n')) = next {
+            self.line += 1;
+        }
+        next
     }
 
     fn peek(&mut self) -> Option<(usize, char)> {
         self.chars.peek().copied()
     }
 
+    fn peek_char(&mut self) -> Option<char> {
+        self.peek().map(|(_, ch)| ch)
+    }
+
     fn eof(&self) -> Token {
         TokenKind::Eof.into_token(self.source.len())
     }
@@ -85,6 +327,518 @@ fn is_space(ch: char) -> bool {
     matches!(ch, ' ' | '\t' | '\n' | '\r')
 }
 
+/// Length in bytes of the `(...)` group `source` starts with, as in the `$(...)` of a command
+/// substitution, counting nested parentheses and skipping quoted text. `None` when it isn't closed.
+pub fn command_substitution_len(source: &str) -> Option<usize> {
+    let mut depth = 0;
+    let mut quote = None;
+    let mut chars = source.char_indices();
+
+    while let Some((byte_pos, ch)) = chars.next() {
+        match (quote, ch) {
+            (Some('"'), '\\') => _ = chars.next(),
+            (Some(open), ch) if ch == open => quote = None,
+            (Some(_), _) => {}
+            (None, '\'' | '"') => quote = Some(ch),
+            (None, '(') => depth += 1,
+            (None, ')') => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(byte_pos + 1);
+                }
+            }
+            (None, _) => {}
+        }
+    }
+
+    None
+}
+
+/// Length in bytes of the identifier at the start of `source`, zero when there is none.
+fn identifier_len(source: &str) -> usize {
+    if !source.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+        return 0;
+    }
+    source
+        .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+        .unwrap_or(source.len())
+}
+
 fn is_delimiter(ch: char) -> bool {
-    is_space(ch) || matches!(ch, '|' | ';' | '&')
+    is_space(ch) || matches!(ch, '|' | ';' | '&' | '>' | '<' | '(' | ')')
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+
+    fn lex(source: &str) -> Vec<(TokenKind, &str)> {
+        Lexer::new(source)
+            .lex()
+            .into_iter()
+            .map(|token| (token.kind(), token.span().slice(source)))
+            .collect()
+    }
+
+    #[test]
+    fn test_single_quoted_atom() {
+        assert_eq!(
+            lex("echo 'a b c'"),
+            [
+                (TokenKind::Atom, "echo"),
+                (TokenKind::QuotedAtom, "a b c"),
+                (TokenKind::Eof, "")
+            ]
+        );
+        assert_eq!(
+            lex("echo '|;&' ''"),
+            [
+                (TokenKind::Atom, "echo"),
+                (TokenKind::QuotedAtom, "|;&"),
+                (TokenKind::QuotedAtom, ""),
+                (TokenKind::Eof, "")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unterminated_single_quote() {
+        assert_eq!(
+            lex("echo 'a b"),
+            [
+                (TokenKind::Atom, "echo"),
+                (TokenKind::UnterminatedQuote, "a b"),
+                (TokenKind::Eof, "")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_double_quoted_atom() {
+        assert_eq!(
+            lex(r#"grep "foo bar" file"#),
+            [
+                (TokenKind::Atom, "grep"),
+                (TokenKind::QuotedAtom, "foo bar"),
+                (TokenKind::Atom, "file"),
+                (TokenKind::Eof, "")
+            ]
+        );
+        assert_eq!(lex(r#""a'b""#), [(TokenKind::QuotedAtom, "a'b"), (TokenKind::Eof, "")]);
+        assert_eq!(lex(r#"'a"b'"#), [(TokenKind::QuotedAtom, "a\"b"), (TokenKind::Eof, "")]);
+        assert_eq!(
+            lex(r#""a \"b\" c""#),
+            [(TokenKind::QuotedAtom, r#"a \"b\" c"#), (TokenKind::Eof, "")]
+        );
+    }
+
+    #[test]
+    fn test_unterminated_double_quote() {
+        assert_eq!(
+            lex(r#"echo "a 'b' \""#),
+            [
+                (TokenKind::Atom, "echo"),
+                (TokenKind::UnterminatedQuote, r#"a 'b' \""#),
+                (TokenKind::Eof, "")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_quotes_inside_words() {
+        assert_eq!(
+            lex(r#"echo foo"bar baz" 'a'b"c;d"e x\"y"#),
+            [
+                (TokenKind::Atom, "echo"),
+                (TokenKind::Atom, r#"foo"bar baz""#),
+                (TokenKind::Atom, r#"'a'b"c;d"e"#),
+                (TokenKind::Atom, r#"x\"y"#),
+                (TokenKind::Eof, "")
+            ]
+        );
+        assert_eq!(
+            lex(r#"echo foo"bar | x"#),
+            [
+                (TokenKind::Atom, "echo"),
+                (TokenKind::UnterminatedQuote, r#"foo"bar | x"#),
+                (TokenKind::Eof, "")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_and_or_operators() {
+        let kinds = |source| lex(source).into_iter().map(|(kind, _)| kind).collect::<Vec<_>>();
+
+        assert_eq!(
+            lex("a && b"),
+            [
+                (TokenKind::Atom, "a"),
+                (TokenKind::AndIf, "&&"),
+                (TokenKind::Atom, "b"),
+                (TokenKind::Eof, "")
+            ]
+        );
+        assert_eq!(
+            lex("a||b"),
+            [
+                (TokenKind::Atom, "a"),
+                (TokenKind::OrIf, "||"),
+                (TokenKind::Atom, "b"),
+                (TokenKind::Eof, "")
+            ]
+        );
+        assert_eq!(
+            kinds("a|b"),
+            [TokenKind::Atom, TokenKind::Pipe, TokenKind::Atom, TokenKind::Eof]
+        );
+        assert_eq!(kinds("a &"), [TokenKind::Atom, TokenKind::Ampersand, TokenKind::Eof]);
+        assert_eq!(
+            kinds("a &&& b"),
+            [
+                TokenKind::Atom,
+                TokenKind::AndIf,
+                TokenKind::Ampersand,
+                TokenKind::Atom,
+                TokenKind::Eof
+            ]
+        );
+    }
+
+    #[test]
+    fn test_redirection_operators() {
+        let out = [
+            (TokenKind::Atom, "cmd"),
+            (TokenKind::Great, ">"),
+            (TokenKind::Atom, "out"),
+            (TokenKind::Eof, ""),
+        ];
+        assert_eq!(lex("cmd > out"), out);
+        assert_eq!(lex("cmd>out"), out);
+
+        let append = [
+            (TokenKind::Atom, "cmd"),
+            (TokenKind::DGreat, ">>"),
+            (TokenKind::Atom, "out"),
+            (TokenKind::Eof, ""),
+        ];
+        assert_eq!(lex("cmd >> out"), append);
+        assert_eq!(lex("cmd>>out"), append);
+
+        let input = [
+            (TokenKind::Atom, "cmd"),
+            (TokenKind::Less, "<"),
+            (TokenKind::Atom, "in"),
+            (TokenKind::Eof, ""),
+        ];
+        assert_eq!(lex("cmd < in"), input);
+        assert_eq!(lex("cmd<in"), input);
+
+        assert_eq!(
+            lex("echo hi>file"),
+            [
+                (TokenKind::Atom, "echo"),
+                (TokenKind::Atom, "hi"),
+                (TokenKind::Great, ">"),
+                (TokenKind::Atom, "file"),
+                (TokenKind::Eof, "")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_fd_redirections() {
+        assert_eq!(
+            lex("ls 2> err"),
+            [
+                (TokenKind::Atom, "ls"),
+                (TokenKind::IoNumber, "2"),
+                (TokenKind::Great, ">"),
+                (TokenKind::Atom, "err"),
+                (TokenKind::Eof, "")
+            ]
+        );
+        assert_eq!(
+            lex("ls 2>>err"),
+            [
+                (TokenKind::Atom, "ls"),
+                (TokenKind::IoNumber, "2"),
+                (TokenKind::DGreat, ">>"),
+                (TokenKind::Atom, "err"),
+                (TokenKind::Eof, "")
+            ]
+        );
+        assert_eq!(
+            lex("ls 2>&1"),
+            [
+                (TokenKind::Atom, "ls"),
+                (TokenKind::IoNumber, "2"),
+                (TokenKind::GreatAnd, ">&"),
+                (TokenKind::Atom, "1"),
+                (TokenKind::Eof, "")
+            ]
+        );
+        assert_eq!(
+            lex("echo 2 > file"),
+            [
+                (TokenKind::Atom, "echo"),
+                (TokenKind::Atom, "2"),
+                (TokenKind::Great, ">"),
+                (TokenKind::Atom, "file"),
+                (TokenKind::Eof, "")
+            ]
+        );
+        assert_eq!(lex("echo a2>f")[1], (TokenKind::Atom, "a2"));
+    }
+
+    #[test]
+    fn test_parens() {
+        assert_eq!(
+            lex("(ls;pwd)"),
+            [
+                (TokenKind::LParen, "("),
+                (TokenKind::Atom, "ls"),
+                (TokenKind::Semi, ""),
+                (TokenKind::Atom, "pwd"),
+                (TokenKind::RParen, ")"),
+                (TokenKind::Eof, "")
+            ]
+        );
+        assert_eq!(lex("echo '(a)'")[1], (TokenKind::QuotedAtom, "(a)"));
+    }
+
+    #[test]
+    fn test_assignments() {
+        assert_eq!(
+            lex("A=1 B=\"x y\" env"),
+            [
+                (TokenKind::Assignment, "A"),
+                (TokenKind::Atom, "1"),
+                (TokenKind::Assignment, "B"),
+                (TokenKind::QuotedAtom, "x y"),
+                (TokenKind::Atom, "env"),
+                (TokenKind::Eof, "")
+            ]
+        );
+        assert_eq!(
+            lex("X= ls"),
+            [
+                (TokenKind::Assignment, "X"),
+                (TokenKind::Atom, ""),
+                (TokenKind::Atom, "ls"),
+                (TokenKind::Eof, "")
+            ]
+        );
+        assert_eq!(
+            lex("P=$HOME;X="),
+            [
+                (TokenKind::Assignment, "P"),
+                (TokenKind::Variable, "HOME"),
+                (TokenKind::Semi, ""),
+                (TokenKind::Assignment, "X"),
+                (TokenKind::Atom, ""),
+                (TokenKind::Eof, "")
+            ]
+        );
+        assert_eq!(lex("a=b=c")[1], (TokenKind::Atom, "b=c"));
+        assert_eq!(lex("=x")[0], (TokenKind::Atom, "=x"));
+        assert_eq!(lex("1x=y")[0], (TokenKind::Atom, "1x=y"));
+    }
+
+    #[test]
+    fn test_bang() {
+        assert_eq!(
+            lex("! false"),
+            [(TokenKind::Bang, "!"), (TokenKind::Atom, "false"), (TokenKind::Eof, "")]
+        );
+        assert_eq!(
+            lex("echo !"),
+            [(TokenKind::Atom, "echo"), (TokenKind::Bang, "!"), (TokenKind::Eof, "")]
+        );
+        assert_eq!(lex("!ls")[0], (TokenKind::Atom, "!ls"));
+        assert_eq!(lex("a!")[0], (TokenKind::Atom, "a!"));
+    }
+
+    #[test]
+    fn test_line_col() {
+        let source = "ls\necho 'a\nb' | wc\n\n  pwd";
+        let mut lexer = Lexer::new(source);
+        let tokens = lexer
+            .lex()
+            .into_iter()
+            .map(|token| token.span().line_col(source))
+            .collect::<Vec<_>>();
+
+        assert_eq!(tokens, [(1, 1), (2, 1), (2, 7), (3, 4), (3, 6), (5, 3), (5, 6)]);
+        assert_eq!(lexer.line(), 5);
+    }
+
+    #[test]
+    fn test_comments() {
+        assert_eq!(
+            lex("echo hi # trailing 'quote"),
+            [(TokenKind::Atom, "echo"), (TokenKind::Atom, "hi"), (TokenKind::Eof, "")]
+        );
+        assert_eq!(
+            lex("# full line | comment\nls;#"),
+            [(TokenKind::Atom, "ls"), (TokenKind::Semi, ""), (TokenKind::Eof, "")]
+        );
+        assert_eq!(
+            lex("echo a#b '#c'"),
+            [
+                (TokenKind::Atom, "echo"),
+                (TokenKind::Atom, "a#b"),
+                (TokenKind::QuotedAtom, "#c"),
+                (TokenKind::Eof, "")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_variables() {
+        assert_eq!(
+            lex("echo $HOME"),
+            [
+                (TokenKind::Atom, "echo"),
+                (TokenKind::Variable, "HOME"),
+                (TokenKind::Eof, "")
+            ]
+        );
+        assert_eq!(
+            lex("echo ${PATH}|wc"),
+            [
+                (TokenKind::Atom, "echo"),
+                (TokenKind::Variable, "PATH"),
+                (TokenKind::Pipe, ""),
+                (TokenKind::Atom, "wc"),
+                (TokenKind::Eof, "")
+            ]
+        );
+        assert_eq!(
+            lex("echo $"),
+            [(TokenKind::Atom, "echo"), (TokenKind::Atom, "$"), (TokenKind::Eof, "")]
+        );
+        assert_eq!(lex("echo $1")[1], (TokenKind::Atom, "$1"));
+        assert_eq!(lex("echo $?")[1], (TokenKind::Variable, "?"));
+        assert_eq!(
+            lex("echo $$ ${!}")[1..3],
+            [(TokenKind::Variable, "$"), (TokenKind::Variable, "!")]
+        );
+        assert_eq!(lex("echo $?x")[1], (TokenKind::Atom, "$?x"));
+        assert_eq!(lex("echo ${A")[1], (TokenKind::Atom, "${A"));
+        assert_eq!(lex("echo $HOME/bin")[1], (TokenKind::Atom, "$HOME/bin"));
+        assert_eq!(lex("echo '$HOME'")[1], (TokenKind::QuotedAtom, "$HOME"));
+    }
+
+    #[test]
+    fn test_command_substitutions() {
+        assert_eq!(
+            lex("echo $(echo a; b) c"),
+            [
+                (TokenKind::Atom, "echo"),
+                (TokenKind::Atom, "$(echo a; b)"),
+                (TokenKind::Atom, "c"),
+                (TokenKind::Eof, "")
+            ]
+        );
+        assert_eq!(
+            lex("a$(b $(c) ')' \")\")x|y"),
+            [
+                (TokenKind::Atom, "a$(b $(c) ')' \")\")x"),
+                (TokenKind::Pipe, ""),
+                (TokenKind::Atom, "y"),
+                (TokenKind::Eof, "")
+            ]
+        );
+        assert_eq!(lex("$(a"), [(TokenKind::Atom, "$(a"), (TokenKind::Eof, "")]);
+        assert_eq!(command_substitution_len("(a (b)) c"), Some(7));
+        assert_eq!(command_substitution_len("(a"), None);
+    }
+
+    #[test]
+    fn test_tokens_are_lazy() {
+        let source = "ls -la | wc\n".repeat(10_000);
+        let mut lexer = Lexer::new(&source);
+
+        let kinds = lexer.tokens().take(3).map(|token| token.kind()).collect::<Vec<_>>();
+        assert_eq!(kinds, [TokenKind::Atom, TokenKind::Atom, TokenKind::Pipe]);
+        assert_eq!(lexer.line(), 1);
+
+        let rest = lexer.tokens().collect::<Vec<_>>();
+        assert_eq!(rest.len(), 10_000 * 4 - 3 + 1);
+        assert_eq!(rest.last().map(|token| token.kind()), Some(TokenKind::Eof));
+    }
+
+    #[test]
+    fn test_token_at() {
+        let source = "ls -la && echo 'a b';wc\ncat  file";
+        let token_at = |pos| Lexer::token_at(source, pos).map(|token| (token.kind(), token.span().slice(source)));
+
+        assert_eq!(token_at(0), Some((TokenKind::Atom, "ls")));
+        assert_eq!(token_at(5), Some((TokenKind::Atom, "-la")));
+        assert_eq!(token_at(7), Some((TokenKind::AndIf, "&&")));
+        assert_eq!(token_at(8), Some((TokenKind::AndIf, "&&")));
+        assert_eq!(token_at(17), Some((TokenKind::QuotedAtom, "a b")));
+        assert_eq!(token_at(20), Some((TokenKind::Semi, "")));
+        assert_eq!(token_at(25), Some((TokenKind::Atom, "cat")));
+        assert_eq!(token_at(30), Some((TokenKind::Atom, "file")));
+
+        assert_eq!(token_at(2), None);
+        assert_eq!(token_at(28), None);
+        assert_eq!(token_at(source.len()), None);
+        assert_eq!(token_at(source.len() + 1), None);
+    }
+
+    /// Characters the grammar cares about, multibyte ones included to catch slicing off a char boundary.
+    static CHARS: [char; 24] = [
+        'a',
+        '1',
+        ' ',
+        '\n',
+        ';',
+        '&',
+        '|',
+        '<',
+        '>',
+        '(',
+        ')',
+        '\'',
+        '"',
+        '\\',
+        '$',
+        '{',
+        '}',
+        '=',
+        '!',
+        '#',
+        '2',
+        'é',
+        '日',
+        '\u{1f980}',
+    ];
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(2_000))]
+
+        #[test]
+        fn test_arbitrary_input_does_not_panic(chars in prop::collection::vec(prop::sample::select(&CHARS[..]), 0..24)) {
+            let source = String::from_iter(chars);
+
+            // tokens can't outnumber the characters they are lexed from, `Eof` aside, so a stream
+            // running past that never ends
+            let tokens = Lexer::new(&source).tokens().take(source.len() + 2).collect::<Vec<_>>();
+            prop_assert!(tokens.len() <= source.len() + 1, "`{}` lexes into endless tokens", source);
+            prop_assert_eq!(tokens.last().map(|token| token.kind()), Some(TokenKind::Eof));
+
+            for token in tokens {
+                _ = (token.span().slice(&source), token.span().line_col(&source));
+            }
+            for pos in 0..=source.len() + 1 {
+                _ = Lexer::token_at(&source, pos);
+                _ = source.get(pos..).map(command_substitution_len);
+            }
+        }
+    }
 }
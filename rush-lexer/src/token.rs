@@ -37,6 +37,15 @@ impl Span {
     pub fn is_empty(&self) -> bool {
         self.len() == 0
     }
+
+    /// 1-based line and column (in chars) where the span starts, resolved on demand so tokens
+    /// don't have to carry them around.
+    pub fn line_col(&self, source: &str) -> (usize, usize) {
+        let before = &source[..*self.start];
+        let line = before.matches('\n').count() + 1;
+        let line_start = before.rfind('\n').map_or(0, |idx| idx + 1);
+        (line, before[line_start..].chars().count() + 1)
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
@@ -55,9 +64,38 @@ impl Token {
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum TokenKind {
     Atom,
+    /// An atom written between single or double quotes, spanning the text between them.
+    QuotedAtom,
     Pipe,
     Semi,
     Ampersand,
+    /// `&&`
+    AndIf,
+    /// `||`
+    OrIf,
+    /// `>`
+    Great,
+    /// `>>`
+    DGreat,
+    /// `<`
+    Less,
+    /// `>&`
+    GreatAnd,
+    /// A `!` standing on its own, which negates the pipeline it starts.
+    Bang,
+    /// `(`
+    LParen,
+    /// `)`
+    RParen,
+    /// `$NAME` or `${NAME}`, spanning just the name, which can also be one of the special
+    /// parameters `?`, `$` and `!`.
+    Variable,
+    /// `NAME=` at the start of a word, spanning just the name. The value follows as its own token.
+    Assignment,
+    /// Digits right before a redirection operator, naming the fd it redirects.
+    IoNumber,
+    /// A quoted string missing its closing quote.
+    UnterminatedQuote,
     Eof,
 }
 
@@ -110,9 +148,9 @@ pub struct TokenStream {
 }
 
 impl TokenStream {
-    pub fn new(tokens: Vec<Token>, eof: impl Into<BytePos>) -> Self {
+    pub fn new(tokens: impl IntoIterator<Item = Token>, eof: impl Into<BytePos>) -> Self {
         Self {
-            tokens,
+            tokens: tokens.into_iter().collect(),
             cursor: 0,
             eof: eof.into(),
         }
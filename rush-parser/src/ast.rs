@@ -1,6 +1,6 @@
-use rush_lexer::{Span, TokenKind};
+use rush_lexer::{Span, Token, TokenKind};
 
-use crate::result::{Error, Result};
+use crate::BindingPower;
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Ast {
@@ -8,34 +8,127 @@ pub enum Ast {
     Pipeline(Vec<SimpleCommand>),
     BackgroundJob(Box<Ast>),
     Sequence(Vec<Ast>),
+    /// `! pipeline`, which succeeds when the pipeline fails and the other way around.
+    Not(Box<Ast>),
+    /// `(ast)`, run in a child process so it can't change the shell's own state.
+    Subshell(Box<Ast>),
+    /// `left && right` or `left || right`, where whether `right` runs depends on how `left` exited.
+    AndOr {
+        left: Box<Ast>,
+        op: AndOrOp,
+        right: Box<Ast>,
+    },
 }
 
-impl Ast {
-    pub fn into_command(self) -> Result<SimpleCommand> {
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AndOrOp {
+    /// `&&`
+    And,
+    /// `||`
+    Or,
+}
+
+impl std::fmt::Display for AndOrOp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Self::Command(cmd) => Ok(cmd),
-            _ => Err(Error::ExpectedCommand(TokenKind::Atom)), // TODO: better error
+            Self::And => write!(f, "&&"),
+            Self::Or => write!(f, "||"),
         }
     }
 }
 
-#[derive(Debug, Clone, PartialEq)]
-pub struct SimpleCommand {
-    pub program: Span,
-    pub args: Vec<Span>,
-}
+impl Ast {
+    pub fn into_command(self) -> Option<SimpleCommand> {
+        match self {
+            Self::Command(cmd) => Some(cmd),
+            _ => None,
+        }
+    }
 
-pub trait DisplayAst {
-    fn to_string(&self, source: &str) -> String;
-}
+    /// Splits a sequence into its statements, any other node is a single statement.
+    pub fn into_statements(self) -> Vec<Ast> {
+        match self {
+            Self::Sequence(seq) => seq,
+            ast => vec![ast],
+        }
+    }
 
-impl DisplayAst for Ast {
-    fn to_string(&self, source: &str) -> String {
+    /// Iterates over the statements of a sequence, any other node is a single statement.
+    pub fn statements(&self) -> std::slice::Iter<'_, Ast> {
+        match self {
+            Self::Sequence(seq) => seq.iter(),
+            ast => std::slice::from_ref(ast).iter(),
+        }
+    }
+
+    /// Commands of a pipeline, a simple command being a pipeline of a single stage.
+    pub fn pipeline_commands(&self) -> Option<&[SimpleCommand]> {
+        match self {
+            Self::Command(cmd) => Some(std::slice::from_ref(cmd)),
+            Self::Pipeline(cmds) => Some(cmds),
+            _ => None,
+        }
+    }
+
+    /// How tightly the operator that produced this node binds, mirroring the parser's binding powers.
+    fn binding_power(&self) -> BindingPower {
+        match self {
+            Self::Command(_) | Self::Subshell(_) => BindingPower::PRIMARY,
+            Self::Pipeline(_) | Self::Not(_) => BindingPower::PIPELINE,
+            Self::AndOr { .. } => BindingPower::AND_OR,
+            Self::BackgroundJob(_) => BindingPower::BACKGROUND,
+            Self::Sequence(_) => BindingPower::SEQUENCE,
+        }
+    }
+
+    /// The tree its formatted string parses back into. The shell has no grouping but subshells, so
+    /// a node that binds looser than where it sits, like the sequence of a background job, comes
+    /// back wrapped in [`Ast::Subshell`]; a sequence nested in another one is flattened into it
+    /// instead, which runs the same.
+    pub fn grouped(&self) -> Ast {
+        // a node binding looser than `min_bp` would be taken apart by the operator around it
+        let group = |ast: &Ast, min_bp: BindingPower| match ast.grouped() {
+            ast if ast.binding_power() < min_bp => Self::Subshell(Box::new(ast)),
+            ast => ast,
+        };
+
+        match self {
+            Self::Command(_) | Self::Pipeline(_) => self.clone(),
+            // `&` ends the job it follows, so another job or a sequence in it has to be grouped
+            Self::BackgroundJob(ast) => Self::BackgroundJob(Box::new(group(ast, BindingPower::AND_OR))),
+            Self::Not(ast) => Self::Not(Box::new(group(ast, BindingPower::PIPELINE))),
+            Self::Subshell(ast) => Self::Subshell(Box::new(ast.grouped())),
+            // lists are left associative, so only a list on the right needs grouping
+            Self::AndOr { left, op, right } => Self::AndOr {
+                left: Box::new(group(left, BindingPower::AND_OR)),
+                op: *op,
+                right: Box::new(group(right, BindingPower::PIPELINE)),
+            },
+            Self::Sequence(seq) => Self::Sequence(
+                seq.iter()
+                    .flat_map(|ast| match ast.grouped() {
+                        Self::Sequence(seq) => seq,
+                        ast => vec![ast],
+                    })
+                    .collect(),
+            ),
+        }
+    }
+
+    /// Formats the node as it is, without grouping anything.
+    fn to_ungrouped_string(&self, source: &str) -> String {
         let mut formatted = String::new();
 
         match self {
-            Self::BackgroundJob(ast) => formatted.push_str(&format!("{} &", ast.to_string(source))),
+            Self::BackgroundJob(ast) => formatted.push_str(&format!("{} &", ast.to_ungrouped_string(source))),
             Self::Command(cmd) => formatted.push_str(&cmd.to_string(source)),
+            Self::Not(ast) => formatted.push_str(&format!("! {}", ast.to_ungrouped_string(source))),
+            Self::Subshell(ast) => formatted.push_str(&format!("({})", ast.to_ungrouped_string(source))),
+            Self::AndOr { left, op, right } => formatted.push_str(&format!(
+                "{} {op} {}",
+                left.to_ungrouped_string(source),
+                right.to_ungrouped_string(source)
+            )),
             Self::Pipeline(cmds) => cmds.iter().enumerate().for_each(|(i, cmd)| {
                 let cmd = cmd.to_string(source);
                 let is_last = i == cmds.len() - 1;
@@ -43,7 +136,7 @@ impl DisplayAst for Ast {
                 formatted.push_str(&format!("{cmd}{sep}"))
             }),
             Self::Sequence(seq) => seq.iter().enumerate().for_each(|(i, ast)| {
-                let cmd = ast.to_string(source);
+                let cmd = ast.to_ungrouped_string(source);
                 let is_last = i == seq.len() - 1;
                 let sep = if is_last { "" } else { "; " };
                 formatted.push_str(&format!("{cmd}{sep}"))
@@ -54,18 +147,167 @@ impl DisplayAst for Ast {
     }
 }
 
+#[derive(Debug, Clone, PartialEq)]
+pub struct SimpleCommand {
+    /// `NAME=value` words before the program name, as the name and the value.
+    pub assignments: Vec<(Span, Word)>,
+    /// Empty and unquoted when the command only has assignments.
+    pub program: Word,
+    pub args: Vec<Word>,
+    pub redirects: Vec<Redirect>,
+}
+
+/// A program name or argument. Quoted words only span the text between their quotes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Word {
+    pub span: Span,
+    /// Whether the word came from quoted text, which later expansions such as globbing leave alone.
+    pub quoted: bool,
+}
+
+impl SimpleCommand {
+    /// A command like `X=5`, made only of assignments and without a program to run.
+    pub fn is_assignment_only(&self) -> bool {
+        !self.assignments.is_empty() && self.program.span.is_empty() && !self.program.quoted
+    }
+
+    /// Program name as it is written, see [`SimpleCommand::arg_values`].
+    pub fn program_name<'src>(&self, source: &'src str) -> &'src str {
+        self.program.value(source)
+    }
+
+    /// Arguments as they are written, without their quotes. Variables and other expansions are
+    /// left as their literal `$NAME` text, the runner expands them.
+    pub fn arg_values<'src>(&self, source: &'src str) -> Vec<&'src str> {
+        self.args.iter().map(|arg| arg.value(source)).collect()
+    }
+}
+
+/// `[fd]op target`, such as `> out`, `2>> log` or `2>&1`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Redirect {
+    /// The io number written before the operator, if any.
+    pub fd: Option<Span>,
+    pub op: RedirectOp,
+    pub target: Word,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RedirectOp {
+    /// `>`
+    Output,
+    /// `>>`
+    Append,
+    /// `<`
+    Input,
+    /// `>&`
+    DuplicateOutput,
+}
+
+impl Redirect {
+    /// The fd being redirected, defaulting to stdin for input and stdout otherwise. `None` when
+    /// the io number doesn't fit an fd.
+    pub fn fd_number(&self, source: &str) -> Option<u32> {
+        match self.fd {
+            Some(fd) => fd.slice(source).parse().ok(),
+            None if self.op == RedirectOp::Input => Some(0),
+            None => Some(1),
+        }
+    }
+}
+
+impl std::fmt::Display for RedirectOp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Output => write!(f, ">"),
+            Self::Append => write!(f, ">>"),
+            Self::Input => write!(f, "<"),
+            Self::DuplicateOutput => write!(f, ">&"),
+        }
+    }
+}
+
+impl Word {
+    /// Text of the word as it is written, see [`SimpleCommand::arg_values`].
+    pub fn value<'src>(&self, source: &'src str) -> &'src str {
+        word_value(self.span, source)
+    }
+
+    /// The quote character around a quoted word, `'` or `"`.
+    pub fn quote(&self, source: &str) -> Option<char> {
+        self.quoted
+            .then(|| source[..*self.span.start].chars().next_back())
+            .flatten()
+    }
+
+    /// Name of the variable for words lexed from `$NAME` or `${NAME}`.
+    pub fn variable_name<'src>(&self, source: &'src str) -> Option<&'src str> {
+        let is_variable = !self.quoted && word_value(self.span, source).len() != self.span.len();
+        is_variable.then(|| self.span.slice(source))
+    }
+
+    pub(crate) fn from_token(token: Token) -> Self {
+        Self {
+            span: token.span(),
+            quoted: token.kind() == TokenKind::QuotedAtom,
+        }
+    }
+}
+
+pub trait DisplayAst {
+    fn to_string(&self, source: &str) -> String;
+}
+
+impl DisplayAst for Ast {
+    /// Formats the tree as source that parses back into [`Ast::grouped`].
+    fn to_string(&self, source: &str) -> String {
+        self.grouped().to_ungrouped_string(source)
+    }
+}
+
 impl DisplayAst for SimpleCommand {
     fn to_string(&self, source: &str) -> String {
-        let name = self.program.slice(source);
-        let args = self
-            .args
+        let assignments = self
+            .assignments
             .iter()
-            .map(|arg| arg.slice(source))
+            .map(|(name, value)| format!("{}={}", name.slice(source), atom_source(*value, source)));
+
+        let program = (!self.is_assignment_only()).then(|| atom_source(self.program, source).to_string());
+        let args = self.args.iter().map(|arg| atom_source(*arg, source).to_string());
+
+        let redirects = self.redirects.iter().map(|redirect| {
+            let fd = redirect.fd.map_or("", |fd| fd.slice(source));
+            let target = atom_source(redirect.target, source);
+            match redirect.op {
+                // `2>&1` reads better without the space
+                RedirectOp::DuplicateOutput => format!("{fd}{}{target}", redirect.op),
+                op => format!("{fd}{op} {target}"),
+            }
+        });
+
+        assignments
+            .chain(program)
+            .chain(args)
+            .chain(redirects)
             .collect::<Vec<_>>()
-            .join(" ");
+            .join(" ")
+    }
+}
 
-        let args = if !args.is_empty() { format!(" {args}") } else { args };
+/// Source text of a word, quoted words only span their contents so the quotes are added back.
+fn atom_source(word: Word, source: &str) -> &str {
+    let (start, end) = (*word.span.start, *word.span.end);
+    if word.quoted { &source[start - 1..end + 1] } else { word_value(word.span, source) }
+}
 
-        format!("{name}{args}")
+/// Value of a word, variables only span their name so the `$` and braces are added back.
+fn word_value(span: Span, source: &str) -> &str {
+    let (start, end) = (*span.start, *span.end);
+    if source[..start].ends_with("${") && source[end..].starts_with('}') {
+        &source[start - 2..end + 1]
+    } else if source[..start].ends_with('$') {
+        &source[start - 1..end]
+    } else {
+        span.slice(source)
     }
 }
@@ -1,23 +1,27 @@
 mod ast;
 mod result;
 
-pub use ast::{Ast, DisplayAst, SimpleCommand};
-use result::{Error, Result};
-use rush_lexer::{TokenKind, TokenStream};
+pub use ast::{AndOrOp, Ast, DisplayAst, Redirect, RedirectOp, SimpleCommand, Word};
+pub use result::Error;
+use result::Result;
+use rush_lexer::{Span, TokenKind, TokenStream};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 struct BindingPower(u8);
 
 impl BindingPower {
+    const AND_OR: BindingPower = BindingPower(25);
     const BACKGROUND: BindingPower = BindingPower(20);
     const MIN: BindingPower = BindingPower(0);
     const PIPELINE: BindingPower = BindingPower(30);
+    const PRIMARY: BindingPower = BindingPower(u8::MAX);
     const SEQUENCE: BindingPower = BindingPower(10);
 
     fn operator_binding_power(token: TokenKind) -> Option<BindingPower> {
         match token {
             TokenKind::Semi => Some(BindingPower::SEQUENCE),
             TokenKind::Ampersand => Some(BindingPower::BACKGROUND),
+            TokenKind::AndIf | TokenKind::OrIf => Some(BindingPower::AND_OR),
             TokenKind::Pipe => Some(BindingPower::PIPELINE),
             _ => None,
         }
@@ -38,18 +42,27 @@ impl Parser {
     }
 
     pub fn parse(&self, mut tokens: TokenStream) -> Result<Ast> {
-        self.parse_expression(&mut tokens, BindingPower::MIN)
+        let ast = self.parse_expression(&mut tokens, BindingPower::MIN)?;
+
+        // anything left over, like a `)` without its `(`, can't continue the expression
+        let token = tokens.next_token();
+        match token.kind() {
+            TokenKind::Eof => Ok(ast),
+            other => Err(Error::UnexpectedToken(other, token.span())),
+        }
     }
 
     fn parse_expression(&self, tokens: &mut TokenStream, min_bp: BindingPower) -> Result<Ast> {
         let mut left = self.parse_primary(tokens)?;
 
         while let Some(operator_binding_power) = BindingPower::operator_binding_power(tokens.peek()) {
-            if operator_binding_power < min_bp {
+            // operators of equal power are left associative, the loop below flattens them
+            if operator_binding_power <= min_bp {
                 break;
             }
 
-            match tokens.next() {
+            let operator = tokens.next_token();
+            match operator.kind() {
                 // ; is a infix operator that denotes a sequence of commands
                 // if the left side is already a sequence, we flatten by pushing the right side
                 TokenKind::Semi if matches!(left, Ast::Sequence(_)) => {
@@ -62,24 +75,45 @@ impl Parser {
                     let right = self.parse_expression(tokens, operator_binding_power)?;
                     left = Ast::Sequence(vec![left, right]);
                 }
+                // && and || are infix operators over pipelines, a background job has already been
+                // sent away and can't be the left side of a list
+                TokenKind::AndIf | TokenKind::OrIf if matches!(left, Ast::BackgroundJob(_)) => {
+                    return Err(Error::UnexpectedToken(operator.kind(), operator.span()));
+                }
+                TokenKind::AndIf | TokenKind::OrIf => {
+                    let op = if operator.kind() == TokenKind::AndIf { AndOrOp::And } else { AndOrOp::Or };
+                    let right = self.parse_expression(tokens, operator_binding_power)?;
+                    left = Ast::AndOr {
+                        left: Box::new(left),
+                        op,
+                        right: Box::new(right),
+                    };
+                }
                 // & is a postfix operator, no right operand is needed.
                 TokenKind::Ampersand => left = Ast::BackgroundJob(Box::new(left)),
                 // | is a infix operator, so it requires both left and right side
                 // if the left side is already a pipeline, we flatten by pushing the right side
                 TokenKind::Pipe if matches!(left, Ast::Pipeline(_)) => {
                     let Ast::Pipeline(mut commands) = left else { unreachable!() };
-                    let right_command = self.parse_expression(tokens, operator_binding_power)?.into_command()?;
+                    let right = self.parse_expression(tokens, operator_binding_power)?;
+                    let right_command = right
+                        .into_command()
+                        .ok_or(Error::ExpectedPipelineCommand(operator.span()))?;
                     commands.push(right_command);
                     left = Ast::Pipeline(commands);
                 }
                 // otherwise, create a pipeline from left and right commands
                 TokenKind::Pipe => {
                     let right = self.parse_expression(tokens, operator_binding_power)?;
-                    let left_cmd = left.into_command()?;
-                    let right_cmd = right.into_command()?;
+                    let left_cmd = left
+                        .into_command()
+                        .ok_or(Error::ExpectedPipelineCommand(operator.span()))?;
+                    let right_cmd = right
+                        .into_command()
+                        .ok_or(Error::ExpectedPipelineCommand(operator.span()))?;
                     left = Ast::Pipeline(vec![left_cmd, right_cmd]);
                 }
-                op => return Err(Error::UnexpectedToken(op)),
+                op => return Err(Error::UnexpectedToken(op, operator.span())),
             }
         }
 
@@ -87,38 +121,131 @@ impl Parser {
     }
 
     fn parse_primary(&self, tokens: &mut TokenStream) -> Result<Ast> {
-        match tokens.peek() {
-            TokenKind::Atom => Ok(Ast::Command(self.parse_command(tokens)?)),
-            TokenKind::Eof => Err(Error::UnexpectedEof),
-            other => Err(Error::ExpectedCommand(other)),
+        let token = tokens.peek_token();
+        match token.kind() {
+            TokenKind::Atom | TokenKind::QuotedAtom | TokenKind::Variable | TokenKind::Assignment => {
+                Ok(Ast::Command(self.parse_command(tokens)?))
+            }
+            // `!` negates a whole pipeline, so it binds looser than `|` but tighter than lists
+            TokenKind::Bang => {
+                tokens.next();
+                let pipeline = self.parse_expression(tokens, BindingPower::AND_OR)?;
+                Ok(Ast::Not(Box::new(pipeline)))
+            }
+            TokenKind::LParen => self.parse_subshell(tokens),
+            TokenKind::Eof => Err(Error::UnexpectedEof(token.span())),
+            TokenKind::UnterminatedQuote => Err(Error::UnterminatedQuote(token.span())),
+            other => Err(Error::ExpectedCommand(other, token.span())),
+        }
+    }
+
+    /// `( expression )`, the inner expression is parsed from scratch so any operator can appear
+    /// inside the parentheses.
+    fn parse_subshell(&self, tokens: &mut TokenStream) -> Result<Ast> {
+        tokens.next();
+        let ast = self.parse_expression(tokens, BindingPower::MIN)?;
+
+        let closing = tokens.next_token();
+        match closing.kind() {
+            TokenKind::RParen => Ok(Ast::Subshell(Box::new(ast))),
+            TokenKind::Eof => Err(Error::UnexpectedEof(closing.span())),
+            other => Err(Error::UnexpectedToken(other, closing.span())),
         }
     }
 
     fn parse_command(&self, tokens: &mut TokenStream) -> Result<SimpleCommand> {
-        // expect at least one atom for the program name
-        let program_token = tokens.next_token();
-        let program_span = match program_token.kind() {
-            TokenKind::Atom => program_token.span(),
-            TokenKind::Eof => return Err(Error::UnexpectedEof),
-            other => return Err(Error::ExpectedCommand(other)),
+        let mut assignments = vec![];
+        while tokens.peek() == TokenKind::Assignment {
+            assignments.push(self.parse_assignment(tokens)?);
+        }
+
+        // expect at least one word for the program name, unless the command only assigns
+        let program_token = tokens.peek_token();
+        let program = match program_token.kind() {
+            TokenKind::Atom | TokenKind::QuotedAtom | TokenKind::Variable => Word::from_token(tokens.next_token()),
+            _ if !assignments.is_empty() => Word {
+                span: Span::new(program_token.span().start, program_token.span().start),
+                quoted: false,
+            },
+            TokenKind::Eof => return Err(Error::UnexpectedEof(program_token.span())),
+            TokenKind::UnterminatedQuote => return Err(Error::UnterminatedQuote(program_token.span())),
+            other => return Err(Error::ExpectedCommand(other, program_token.span())),
         };
 
         let mut args = vec![];
-        while matches!(tokens.peek(), TokenKind::Atom) {
-            let arg_token = tokens.next_token();
-            args.push(arg_token.span());
+        let mut redirects = vec![];
+        loop {
+            let arg_token = tokens.peek_token();
+            match arg_token.kind() {
+                // past the program name a `!` is just a word, as in `echo !`
+                TokenKind::Atom | TokenKind::QuotedAtom | TokenKind::Variable | TokenKind::Bang => {
+                    args.push(Word::from_token(tokens.next_token()))
+                }
+                // past the program name an assignment is just a word, as in `env A=1`
+                TokenKind::Assignment => {
+                    let (name, value) = self.parse_assignment(tokens)?;
+                    let end = if value.quoted { *value.span.end + 1 } else { *value.span.end };
+                    args.push(Word {
+                        span: Span::new(name.start, end.into()),
+                        quoted: false,
+                    });
+                }
+                TokenKind::IoNumber | TokenKind::Great | TokenKind::DGreat | TokenKind::Less | TokenKind::GreatAnd => {
+                    redirects.push(self.parse_redirect(tokens)?)
+                }
+                TokenKind::UnterminatedQuote => return Err(Error::UnterminatedQuote(arg_token.span())),
+                _ => break,
+            }
         }
 
         Ok(SimpleCommand {
-            program: program_span,
+            assignments,
+            program,
             args,
+            redirects,
         })
     }
+
+    /// `NAME=value`, the lexer always follows an assignment name with a token for its value.
+    fn parse_assignment(&self, tokens: &mut TokenStream) -> Result<(Span, Word)> {
+        let name = tokens.next_token().span();
+
+        let value_token = tokens.next_token();
+        match value_token.kind() {
+            TokenKind::Atom | TokenKind::QuotedAtom | TokenKind::Variable => Ok((name, Word::from_token(value_token))),
+            TokenKind::UnterminatedQuote => Err(Error::UnterminatedQuote(value_token.span())),
+            other => Err(Error::UnexpectedToken(other, value_token.span())),
+        }
+    }
+
+    /// `[n]op target`, the lexer only emits an io number right before a redirection operator.
+    fn parse_redirect(&self, tokens: &mut TokenStream) -> Result<Redirect> {
+        let fd = (tokens.peek() == TokenKind::IoNumber).then(|| tokens.next_token().span());
+
+        let operator = tokens.next_token();
+        let op = match operator.kind() {
+            TokenKind::Great => RedirectOp::Output,
+            TokenKind::DGreat => RedirectOp::Append,
+            TokenKind::Less => RedirectOp::Input,
+            TokenKind::GreatAnd => RedirectOp::DuplicateOutput,
+            other => return Err(Error::UnexpectedToken(other, operator.span())),
+        };
+
+        let target_token = tokens.next_token();
+        let target = match target_token.kind() {
+            TokenKind::Atom | TokenKind::QuotedAtom | TokenKind::Variable => Word::from_token(target_token),
+            TokenKind::Eof => return Err(Error::UnexpectedEof(target_token.span())),
+            TokenKind::UnterminatedQuote => return Err(Error::UnterminatedQuote(target_token.span())),
+            other => return Err(Error::ExpectedRedirectTarget(other, target_token.span())),
+        };
+
+        Ok(Redirect { fd, op, target })
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use rush_lexer::Span;
+    use proptest::prelude::*;
 
     use super::*;
 
@@ -128,8 +255,10 @@ mod tests {
 
     #[derive(Debug, Clone, PartialEq)]
     struct SimpleCommandSnapshot {
+        assignments: Vec<(Span, Word)>,
         program: Span,
         args: Vec<Span>,
+        redirects: Vec<Redirect>,
         source: String,
     }
 
@@ -139,6 +268,9 @@ mod tests {
         Pipeline(Vec<SimpleCommandSnapshot>),
         BackgroundJob(Box<SnapshotAst>),
         Sequence(Vec<SnapshotAst>),
+        AndOr(Box<SnapshotAst>, AndOrOp, Box<SnapshotAst>),
+        Subshell(Box<SnapshotAst>),
+        Not(Box<SnapshotAst>),
     }
 
     impl SnapshotAst {
@@ -153,9 +285,11 @@ mod tests {
     impl IntoSnapshot for SimpleCommand {
         fn into_snapshot(self, source: &str) -> SnapshotAst {
             SnapshotAst::Command(SimpleCommandSnapshot {
-                program: self.program,
+                program: self.program.span,
                 source: self.to_string(source),
-                args: self.args,
+                assignments: self.assignments,
+                args: self.args.iter().map(|arg| arg.span).collect(),
+                redirects: self.redirects,
             })
         }
     }
@@ -171,9 +305,16 @@ mod tests {
                         .collect(),
                 ),
                 Ast::BackgroundJob(ast) => SnapshotAst::BackgroundJob(Box::new(ast.into_snapshot(source))),
+                Ast::Subshell(ast) => SnapshotAst::Subshell(Box::new(ast.into_snapshot(source))),
+                Ast::Not(ast) => SnapshotAst::Not(Box::new(ast.into_snapshot(source))),
                 Ast::Sequence(asts) => {
                     SnapshotAst::Sequence(asts.into_iter().map(|ast| ast.into_snapshot(source)).collect())
                 }
+                Ast::AndOr { left, op, right } => SnapshotAst::AndOr(
+                    Box::new(left.into_snapshot(source)),
+                    op,
+                    Box::new(right.into_snapshot(source)),
+                ),
             }
         }
     }
@@ -205,4 +346,393 @@ mod tests {
         assert!(matches!(ast, Ast::BackgroundJob(_)));
         insta::assert_debug_snapshot!(ast.into_snapshot(source));
     }
+
+    #[test]
+    fn test_parsing_and_or_list() {
+        let source = "a && b || c";
+        let ast = Parser::new().parse(rush_lexer::Lexer::new(source).lex()).unwrap();
+        assert!(matches!(ast, Ast::AndOr { op: AndOrOp::Or, .. }));
+        insta::assert_debug_snapshot!(ast.into_snapshot(source));
+    }
+
+    #[test]
+    fn test_parsing_and_or_of_pipelines() {
+        let source = "a | b && c";
+        let ast = Parser::new().parse(rush_lexer::Lexer::new(source).lex()).unwrap();
+        assert!(matches!(ast, Ast::AndOr { op: AndOrOp::And, .. }));
+        insta::assert_debug_snapshot!(ast.into_snapshot(source));
+
+        let source = "a && b | c &";
+        let ast = Parser::new().parse(rush_lexer::Lexer::new(source).lex()).unwrap();
+        assert_eq!(
+            shape(&ast, source),
+            "BackgroundJob(AndOr(Command(a) && Pipeline(b, c)))"
+        );
+
+        let source = "a & && b";
+        let error = Parser::new().parse(rush_lexer::Lexer::new(source).lex()).unwrap_err();
+        assert!(matches!(error, Error::UnexpectedToken(TokenKind::AndIf, _)));
+    }
+
+    #[test]
+    fn test_parsing_output_redirect() {
+        let source = "echo hi > out";
+        let ast = Parser::new().parse(rush_lexer::Lexer::new(source).lex()).unwrap();
+        insta::assert_debug_snapshot!(ast.into_snapshot(source));
+    }
+
+    #[test]
+    fn test_parsing_input_redirect() {
+        let source = "cat < in";
+        let ast = Parser::new().parse(rush_lexer::Lexer::new(source).lex()).unwrap();
+        insta::assert_debug_snapshot!(ast.into_snapshot(source));
+    }
+
+    #[test]
+    fn test_parsing_fd_redirect() {
+        let source = "cmd 2>> log";
+        let ast = Parser::new().parse(rush_lexer::Lexer::new(source).lex()).unwrap();
+        let command = ast.clone().into_command().unwrap();
+        assert_eq!(command.redirects[0].fd_number(source), Some(2));
+        insta::assert_debug_snapshot!(ast.into_snapshot(source));
+
+        let source = "make 2>&1 > 'build log' | tee";
+        let ast = Parser::new().parse(rush_lexer::Lexer::new(source).lex()).unwrap();
+        assert_eq!(ast.to_string(source), source);
+
+        let source = "ls >";
+        let error = Parser::new().parse(rush_lexer::Lexer::new(source).lex()).unwrap_err();
+        assert!(matches!(error, Error::UnexpectedEof(_)));
+    }
+
+    #[test]
+    fn test_parsing_subshell() {
+        let source = "(a; b)";
+        let ast = Parser::new().parse(rush_lexer::Lexer::new(source).lex()).unwrap();
+        assert!(matches!(ast, Ast::Subshell(_)));
+        insta::assert_debug_snapshot!(ast.into_snapshot(source));
+    }
+
+    #[test]
+    fn test_parsing_background_subshell() {
+        let source = "(a | b) & ";
+        let ast = Parser::new().parse(rush_lexer::Lexer::new(source).lex()).unwrap();
+        assert!(matches!(ast, Ast::BackgroundJob(_)));
+        insta::assert_debug_snapshot!(ast.into_snapshot(source));
+
+        let source = "((a && b) || c); d";
+        let ast = Parser::new().parse(rush_lexer::Lexer::new(source).lex()).unwrap();
+        assert_eq!(
+            shape(&ast, source),
+            "Sequence(Subshell(AndOr(Subshell(AndOr(Command(a) && Command(b))) || Command(c))), Command(d))"
+        );
+        assert_eq!(ast.to_string(source), source);
+
+        let parse = |source| Parser::new().parse(rush_lexer::Lexer::new(source).lex());
+        assert!(matches!(parse("(a; b"), Err(Error::UnexpectedEof(_))));
+        assert!(matches!(parse("a)"), Err(Error::UnexpectedToken(TokenKind::RParen, _))));
+        assert!(matches!(parse("()"), Err(Error::ExpectedCommand(TokenKind::RParen, _))));
+    }
+
+    #[test]
+    fn test_parsing_assignments() {
+        let source = "A=1 B=2 env";
+        let ast = Parser::new().parse(rush_lexer::Lexer::new(source).lex()).unwrap();
+        insta::assert_debug_snapshot!(ast.into_snapshot(source));
+
+        let source = r#"A= B="x y" env C=3 > out"#;
+        let ast = Parser::new().parse(rush_lexer::Lexer::new(source).lex()).unwrap();
+        let command = ast.clone().into_command().unwrap();
+        assert_eq!(command.arg_values(source), ["C=3"]);
+        assert_eq!(ast.to_string(source), source);
+    }
+
+    #[test]
+    fn test_parsing_assignment_only() {
+        let source = "X=5";
+        let ast = Parser::new().parse(rush_lexer::Lexer::new(source).lex()).unwrap();
+        let command = ast.clone().into_command().unwrap();
+        assert!(command.is_assignment_only());
+        assert_eq!(ast.to_string(source), source);
+        insta::assert_debug_snapshot!(ast.into_snapshot(source));
+    }
+
+    #[test]
+    fn test_parsing_not() {
+        let source = "! false";
+        let ast = Parser::new().parse(rush_lexer::Lexer::new(source).lex()).unwrap();
+        assert!(matches!(ast, Ast::Not(_)));
+        insta::assert_debug_snapshot!(ast.into_snapshot(source));
+    }
+
+    #[test]
+    fn test_parsing_not_pipeline() {
+        let source = "! a | b";
+        let ast = Parser::new().parse(rush_lexer::Lexer::new(source).lex()).unwrap();
+        insta::assert_debug_snapshot!(ast.into_snapshot(source));
+
+        let source = "! a && echo !";
+        let ast = Parser::new().parse(rush_lexer::Lexer::new(source).lex()).unwrap();
+        assert_eq!(shape(&ast, source), "AndOr(Not(Command(a)) && Command(echo !))");
+        assert_eq!(ast.to_string(source), source);
+    }
+
+    #[test]
+    fn test_statements_and_pipeline_commands() {
+        let source = "ls -la; echo hi | wc -l; sleep 1 &";
+        let ast = Parser::new().parse(rush_lexer::Lexer::new(source).lex()).unwrap();
+        assert_eq!(ast.statements().count(), 3);
+
+        let statements = ast.into_statements();
+        let commands = statements
+            .iter()
+            .map(|ast| ast.pipeline_commands().map(|cmds| cmds.len()))
+            .collect::<Vec<_>>();
+        assert_eq!(commands, [Some(1), Some(2), None]);
+
+        let source = "echo hi | wc -l";
+        let ast = Parser::new().parse(rush_lexer::Lexer::new(source).lex()).unwrap();
+        let stages = ast.pipeline_commands().unwrap();
+        assert_eq!(stages[1].to_string(source), "wc -l");
+        assert_eq!(ast.into_statements().len(), 1);
+    }
+
+    #[test]
+    fn test_variable_words() {
+        let source = "$EDITOR $HOME ${PATH} '$USER'";
+        let ast = Parser::new().parse(rush_lexer::Lexer::new(source).lex()).unwrap();
+        let command = ast.into_command().unwrap();
+
+        assert_eq!(command.program_name(source), "$EDITOR");
+        assert_eq!(command.arg_values(source), ["$HOME", "${PATH}", "$USER"]);
+        assert_eq!(command.to_string(source), source);
+    }
+
+    #[test]
+    fn test_quoted_words() {
+        let source = r#"echo *.txt "*.txt" '*.md'"#;
+        let ast = Parser::new().parse(rush_lexer::Lexer::new(source).lex()).unwrap();
+        let command = ast.into_command().unwrap();
+
+        assert!(!command.program.quoted);
+        let quoted = command.args.iter().map(|arg| arg.quoted).collect::<Vec<_>>();
+        assert_eq!(quoted, [false, true, true]);
+        let quotes = command.args.iter().map(|arg| arg.quote(source)).collect::<Vec<_>>();
+        assert_eq!(quotes, [None, Some('"'), Some('\'')]);
+        assert_eq!(command.arg_values(source), ["*.txt", "*.txt", "*.md"]);
+        assert_eq!(command.to_string(source), source);
+    }
+
+    /// Renders the shape of a tree without spans, so trees parsed from different sources can be compared.
+    fn shape(ast: &Ast, source: &str) -> String {
+        match ast {
+            Ast::Command(cmd) => format!("Command({})", cmd.to_string(source)),
+            Ast::Pipeline(cmds) => {
+                let cmds = cmds.iter().map(|cmd| cmd.to_string(source)).collect::<Vec<_>>();
+                format!("Pipeline({})", cmds.join(", "))
+            }
+            Ast::BackgroundJob(ast) => format!("BackgroundJob({})", shape(ast, source)),
+            Ast::Subshell(ast) => format!("Subshell({})", shape(ast, source)),
+            Ast::Not(ast) => format!("Not({})", shape(ast, source)),
+            Ast::AndOr { left, op, right } => format!("AndOr({} {op} {})", shape(left, source), shape(right, source)),
+            Ast::Sequence(seq) => {
+                let seq = seq.iter().map(|ast| shape(ast, source)).collect::<Vec<_>>();
+                format!("Sequence({})", seq.join(", "))
+            }
+        }
+    }
+
+    /// Random sources made of a few words and operators, valid or not.
+    fn arbitrary_source() -> impl Strategy<Value = String> {
+        const WORDS: [&str; 8] = ["ls", "-la", "echo", "wc", "'a | b'", r#""c \" d""#, "$HOME", "${PATH}"];
+        const OPERATORS: [&str; 6] = [";", "|", "&", "&&", "||", ""];
+
+        let words = prop::collection::vec(prop::sample::select(&WORDS[..]), 1..=4);
+        let statement = (words, prop::sample::select(&OPERATORS[..]))
+            .prop_map(|(words, operator)| format!("{} {operator} ", words.join(" ")));
+        prop::collection::vec(statement, 1..=7).prop_map(|statements| statements.concat())
+    }
+
+    /// Random trees over the commands `a`, `b` and `c` of the source `"a b c"`, nesting operators
+    /// in ways the parser never produces on its own.
+    fn arbitrary_tree() -> impl Strategy<Value = Ast> {
+        fn command(idx: usize) -> SimpleCommand {
+            SimpleCommand {
+                assignments: vec![],
+                program: Word {
+                    span: Span::new((idx * 2).into(), (idx * 2 + 1).into()),
+                    quoted: false,
+                },
+                args: vec![],
+                redirects: vec![],
+            }
+        }
+
+        let leaf = prop_oneof![
+            (0..3usize).prop_map(|idx| Ast::Command(command(idx))),
+            (0..3usize, 0..3usize).prop_map(|(first, second)| Ast::Pipeline(vec![command(first), command(second)])),
+        ];
+        leaf.prop_recursive(4, 32, 2, |inner| {
+            prop_oneof![
+                inner.clone().prop_map(|ast| Ast::BackgroundJob(Box::new(ast))),
+                inner.clone().prop_map(|ast| Ast::Not(Box::new(ast))),
+                inner.clone().prop_map(|ast| Ast::Subshell(Box::new(ast))),
+                (inner.clone(), any::<bool>(), inner.clone()).prop_map(|(left, and, right)| Ast::AndOr {
+                    left: Box::new(left),
+                    op: if and { AndOrOp::And } else { AndOrOp::Or },
+                    right: Box::new(right),
+                }),
+                prop::collection::vec(inner, 2..=3).prop_map(Ast::Sequence),
+            ]
+        })
+    }
+
+    static CHARS: [char; 26] = [
+        'a', '1', ' ', '\t', '\n', ';', '&', '|', '<', '>', '(', ')', '\'', '"', '\\', '$', '{', '}', '=', '!', '#',
+        '*', '~', '-', 'é', '日',
+    ];
+
+    /// Parses `source` on a thread of its own, so a parser that never finishes fails the test
+    /// instead of hanging it.
+    fn parse_in_time(source: &str) -> Result<Ast> {
+        let (tx, rx) = std::sync::mpsc::channel();
+        let owned = source.to_string();
+        std::thread::spawn(move || _ = tx.send(Parser::new().parse(rush_lexer::Lexer::new(&owned).lex())));
+        rx.recv_timeout(std::time::Duration::from_secs(5))
+            .unwrap_or_else(|_| panic!("parsing `{source}` never finished"))
+    }
+
+    #[test]
+    fn test_formatting_round_trips() {
+        let parsed = std::cell::Cell::new(0);
+        let mut runner = proptest::test_runner::TestRunner::new(ProptestConfig::with_cases(1000));
+
+        runner
+            .run(&arbitrary_source(), |source| {
+                let Ok(ast) = parse_in_time(&source) else { return Ok(()) };
+                parsed.set(parsed.get() + 1);
+
+                let formatted = ast.to_string(&source);
+                let reparsed = parse_in_time(&formatted)
+                    .unwrap_or_else(|e| panic!("`{formatted}` (from `{source}`) failed to re-parse: {e}"));
+
+                prop_assert_eq!(
+                    shape(&ast, &source),
+                    shape(&reparsed, &formatted),
+                    "source: `{}`",
+                    source
+                );
+                Ok(())
+            })
+            .unwrap();
+
+        assert!(parsed.get() > 100, "only {} random sources parsed", parsed.get());
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(2_000))]
+
+        #[test]
+        fn test_formatting_built_trees_round_trips(ast in arbitrary_tree()) {
+            let source = "a b c";
+            let formatted = ast.to_string(source);
+            let reparsed = parse_in_time(&formatted).unwrap_or_else(|e| panic!("`{formatted}` failed to re-parse: {e}"));
+
+            prop_assert_eq!(shape(&reparsed, &formatted), shape(&ast.grouped(), source), "`{}`", formatted);
+        }
+
+        #[test]
+        fn test_arbitrary_input_does_not_panic(chars in prop::collection::vec(prop::sample::select(&CHARS[..]), 0..24)) {
+            let source = String::from_iter(chars);
+            let Ok(ast) = parse_in_time(&source) else { return Ok(()) };
+
+            // every span of the tree has to slice the source it came from
+            _ = ast.to_string(&source);
+            for statement in ast.statements() {
+                for command in statement.pipeline_commands().unwrap_or_default() {
+                    _ = command.program_name(&source);
+                    _ = command.arg_values(&source);
+                    for word in command
+                        .args
+                        .iter()
+                        .chain(command.assignments.iter().map(|(_, value)| value))
+                    {
+                        _ = (word.quote(&source), word.variable_name(&source));
+                    }
+                    for redirect in &command.redirects {
+                        _ = (redirect.fd_number(&source), redirect.target.value(&source));
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_formatting_groups_looser_operators() {
+        let source = "a b";
+        let command = |start: usize| {
+            Ast::Command(SimpleCommand {
+                assignments: vec![],
+                program: Word {
+                    span: Span::new(start.into(), (start + 1).into()),
+                    quoted: false,
+                },
+                args: vec![],
+                redirects: vec![],
+            })
+        };
+        let round_trip = |ast: &Ast| {
+            let formatted = ast.to_string(source);
+            let reparsed = Parser::new().parse(rush_lexer::Lexer::new(&formatted).lex()).unwrap();
+            let reparsed = shape(&reparsed, &formatted);
+            assert_eq!(reparsed, shape(&ast.grouped(), source));
+            (formatted, reparsed)
+        };
+
+        let ast = Ast::BackgroundJob(Box::new(Ast::Sequence(vec![command(0), command(2)])));
+        assert_eq!(
+            round_trip(&ast),
+            (
+                String::from("(a; b) &"),
+                String::from("BackgroundJob(Subshell(Sequence(Command(a), Command(b))))")
+            )
+        );
+
+        let ast = Ast::Sequence(vec![Ast::Sequence(vec![command(0), command(2)]), command(0)]);
+        assert_eq!(
+            round_trip(&ast),
+            (
+                String::from("a; b; a"),
+                String::from("Sequence(Command(a), Command(b), Command(a))")
+            )
+        );
+
+        let and = |left, right| Ast::AndOr {
+            left: Box::new(left),
+            op: AndOrOp::And,
+            right: Box::new(right),
+        };
+        let ast = and(command(0), and(command(2), command(0)));
+        assert_eq!(
+            round_trip(&ast),
+            (
+                String::from("a && (b && a)"),
+                String::from("AndOr(Command(a) && Subshell(AndOr(Command(b) && Command(a))))")
+            )
+        );
+
+        let ast = Ast::Not(Box::new(Ast::BackgroundJob(Box::new(command(0)))));
+        assert_eq!(
+            round_trip(&ast),
+            (
+                String::from("! (a &)"),
+                String::from("Not(Subshell(BackgroundJob(Command(a))))")
+            )
+        );
+
+        // a tree that needs no grouping parses back as it is
+        let ast = and(Ast::Subshell(Box::new(ast.grouped())), command(2));
+        assert_eq!(ast.grouped(), ast);
+        assert_eq!(round_trip(&ast).0, "(! (a &)) && b");
+    }
 }
@@ -1,13 +1,36 @@
+use rush_lexer::{Span, TokenKind};
+
 pub type Result<T, E = Error> = std::result::Result<T, E>;
 
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
     #[error("Unexpected end of input")]
-    UnexpectedEof,
+    UnexpectedEof(Span),
     #[error("Expected command, found {0:?}")]
-    ExpectedCommand(rush_lexer::TokenKind),
+    ExpectedCommand(TokenKind, Span),
+    #[error("Only simple commands can be piped")]
+    ExpectedPipelineCommand(Span),
     #[error("Unexpected token: {0:?}")]
-    UnexpectedToken(rush_lexer::TokenKind),
+    UnexpectedToken(TokenKind, Span),
+    #[error("Expected a file name to redirect to, found {0:?}")]
+    ExpectedRedirectTarget(TokenKind, Span),
+    #[error("Unterminated quoted string")]
+    UnterminatedQuote(Span),
     #[error("Empty command")]
     EmptyCommand,
 }
+
+impl Error {
+    /// Where in the source the error occurred, if it is tied to a position.
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            Self::UnexpectedEof(span)
+            | Self::ExpectedCommand(_, span)
+            | Self::ExpectedPipelineCommand(span)
+            | Self::UnexpectedToken(_, span)
+            | Self::ExpectedRedirectTarget(_, span)
+            | Self::UnterminatedQuote(span) => Some(*span),
+            Self::EmptyCommand => None,
+        }
+    }
+}
@@ -0,0 +1,161 @@
+use crate::result::Result;
+use crate::{ExecCtx, env, history, jobs, options};
+
+/// A command run by the shell itself, given its expanded arguments without the command name.
+pub(crate) type BuiltinFn = fn(&mut ExecCtx<'_>, &[String]) -> Result<i32>;
+
+/// Every builtin but `exec`, which needs the command's redirections rather than having them
+/// applied around it.
+const BUILTINS: &[(&str, BuiltinFn)] = &[
+    ("bg", bg),
+    ("cd", cd),
+    ("echo", echo),
+    ("eval", eval),
+    ("export", export),
+    ("fg", fg),
+    ("history", history),
+    ("jobs", jobs),
+    ("kill", kill),
+    ("pwd", pwd),
+    ("set", set),
+    ("unset", unset),
+    ("wait", wait),
+];
+
+/// The builtin called `name`, if there is one.
+pub(crate) fn lookup(name: &str) -> Option<BuiltinFn> {
+    find(BUILTINS, name)
+}
+
+fn find(table: &[(&str, BuiltinFn)], name: &str) -> Option<BuiltinFn> {
+    table
+        .iter()
+        .find(|(builtin, _)| *builtin == name)
+        .map(|(_, builtin)| *builtin)
+}
+
+/// Splits the options leading `args` from the operands after them. A `--` ends the options and is
+/// dropped, so an operand can start with `-`; a lone `-` already is an operand, as in `cd -`.
+pub(crate) fn split_options<'b, 'a>(args: &'b [&'a str]) -> (&'b [&'a str], &'b [&'a str]) {
+    let end = args
+        .iter()
+        .position(|arg| !arg.starts_with('-') || *arg == "-" || *arg == "--")
+        .unwrap_or(args.len());
+    match args.get(end) {
+        Some(&"--") => (&args[..end], &args[end + 1..]),
+        _ => args.split_at(end),
+    }
+}
+
+fn as_strs(args: &[String]) -> Vec<&str> {
+    args.iter().map(String::as_str).collect()
+}
+
+fn bg(ctx: &mut ExecCtx<'_>, args: &[String]) -> Result<i32> {
+    Ok(jobs::bg(ctx, &as_strs(args)))
+}
+
+fn cd(ctx: &mut ExecCtx<'_>, args: &[String]) -> Result<i32> {
+    Ok(crate::change_directory(ctx, &as_strs(args)))
+}
+
+fn echo(_: &mut ExecCtx<'_>, args: &[String]) -> Result<i32> {
+    Ok(crate::execute_echo(&as_strs(args)))
+}
+
+fn eval(ctx: &mut ExecCtx<'_>, args: &[String]) -> Result<i32> {
+    crate::execute_eval(ctx, &args.join(" "))
+}
+
+fn export(ctx: &mut ExecCtx<'_>, args: &[String]) -> Result<i32> {
+    Ok(env::export(&mut ctx.env.lock().unwrap(), &as_strs(args)))
+}
+
+fn fg(ctx: &mut ExecCtx<'_>, args: &[String]) -> Result<i32> {
+    Ok(jobs::fg(ctx, &as_strs(args)))
+}
+
+fn history(ctx: &mut ExecCtx<'_>, args: &[String]) -> Result<i32> {
+    Ok(history::history(&ctx.history.lock().unwrap(), &as_strs(args)))
+}
+
+fn jobs(ctx: &mut ExecCtx<'_>, _: &[String]) -> Result<i32> {
+    Ok(jobs::jobs(ctx))
+}
+
+fn kill(ctx: &mut ExecCtx<'_>, args: &[String]) -> Result<i32> {
+    Ok(jobs::kill(ctx, &as_strs(args)))
+}
+
+fn pwd(ctx: &mut ExecCtx<'_>, args: &[String]) -> Result<i32> {
+    Ok(crate::execute_pwd(&ctx.env.lock().unwrap(), &as_strs(args)))
+}
+
+fn set(ctx: &mut ExecCtx<'_>, args: &[String]) -> Result<i32> {
+    let mut options = ctx.options.lock().unwrap();
+    let status = options::set(&mut options, &as_strs(args));
+    if options.monitor {
+        crate::ignore_terminal_signals();
+    }
+    Ok(status)
+}
+
+fn unset(ctx: &mut ExecCtx<'_>, args: &[String]) -> Result<i32> {
+    Ok(env::unset(&mut ctx.env.lock().unwrap(), &as_strs(args)))
+}
+
+fn wait(ctx: &mut ExecCtx<'_>, args: &[String]) -> Result<i32> {
+    Ok(jobs::wait(ctx, &as_strs(args)))
+}
+
+#[cfg(test)]
+mod tests {
+    use nix::sys::signal::{self, SigHandler, Signal};
+
+    use super::*;
+    use crate::ShellOptions;
+    use crate::tests::run;
+
+    fn dummy(ctx: &mut ExecCtx<'_>, args: &[String]) -> Result<i32> {
+        ctx.env.lock().unwrap().set("DUMMY", args.join(","));
+        Ok(args.len() as i32)
+    }
+
+    #[test]
+    fn test_lookup() {
+        let table: &[(&str, BuiltinFn)] = &[("dummy", dummy)];
+        let mut ctx = run("true", ShellOptions::new(false));
+
+        let builtin = find(table, "dummy").unwrap();
+        assert_eq!(builtin(&mut ctx, &["a".into(), "b".into()]).unwrap(), 2);
+        assert_eq!(ctx.env.lock().unwrap().get("DUMMY"), Some("a,b"));
+        assert!(find(table, "cd").is_none());
+
+        assert!(lookup("cd").is_some());
+        assert!(lookup("exec").is_none());
+        assert!(lookup("ls").is_none());
+    }
+
+    #[test]
+    fn test_monitor_ignores_terminal_signals() {
+        run("set -o monitor", ShellOptions::new(false));
+
+        for sig in [Signal::SIGTTIN, Signal::SIGTTOU] {
+            let previous = unsafe { signal::signal(sig, SigHandler::SigIgn) }.unwrap();
+            assert_eq!(previous, SigHandler::SigIgn);
+        }
+    }
+
+    #[test]
+    fn test_split_options() {
+        let empty: &[&str] = &[];
+        assert_eq!(split_options(&["-L", "-P", "dir"]), (&["-L", "-P"][..], &["dir"][..]));
+        assert_eq!(
+            split_options(&["-L", "--", "-x", "--"]),
+            (&["-L"][..], &["-x", "--"][..])
+        );
+        assert_eq!(split_options(&["-", "-x"]), (empty, &["-", "-x"][..]));
+        assert_eq!(split_options(&["--"]), (empty, empty));
+        assert_eq!(split_options(empty), (empty, empty));
+    }
+}
@@ -0,0 +1,88 @@
+use crate::Env;
+
+/// Spaces between two columns.
+const COLUMN_GAP: usize = 2;
+
+/// Lays `items` out in as many columns as fit in `width`, filled top to bottom like `ls` does,
+/// each column as wide as its longest item. Every row ends with a newline.
+pub(crate) fn format_columns(items: &[impl AsRef<str>], width: usize) -> String {
+    let lengths = items
+        .iter()
+        .map(|item| item.as_ref().chars().count())
+        .collect::<Vec<_>>();
+
+    let (rows, widths) = (1..=items.len().max(1))
+        .rev()
+        .map(|columns| {
+            let rows = items.len().div_ceil(columns);
+            let widths = lengths
+                .chunks(rows.max(1))
+                .map(|column| column.iter().copied().max().unwrap_or(0));
+            (rows, widths.collect::<Vec<_>>())
+        })
+        .find(|(_, widths)| widths.iter().sum::<usize>() + COLUMN_GAP * widths.len().saturating_sub(1) <= width)
+        // an item wider than the terminal still gets a line of its own
+        .unwrap_or_else(|| (items.len(), vec![lengths.iter().copied().max().unwrap_or(0)]));
+
+    let mut formatted = String::new();
+    for row in 0..rows {
+        let cells = (0..widths.len())
+            .filter_map(|column| Some((column, items.get(column * rows + row)?.as_ref())))
+            .collect::<Vec<_>>();
+
+        for (i, (column, item)) in cells.iter().enumerate() {
+            formatted.push_str(item);
+            if i + 1 < cells.len() {
+                let padding = widths[*column] - lengths[column * rows + row] + COLUMN_GAP;
+                formatted.push_str(&" ".repeat(padding));
+            }
+        }
+        formatted.push('\n');
+    }
+
+    formatted
+}
+
+/// Width listings should fit in, from the shell's `$COLUMNS`, then the terminal on stdout. Output
+/// that isn't a terminal has no width, so listings go one item per line, as `ls` does.
+pub(crate) fn terminal_width(env: &Env) -> usize {
+    if let Some(columns) = env.get("COLUMNS").and_then(|columns| columns.parse().ok())
+        && columns > 0
+    {
+        return columns;
+    }
+
+    let mut size = nix::libc::winsize {
+        ws_row: 0,
+        ws_col: 0,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    };
+    match unsafe { nix::libc::ioctl(nix::libc::STDOUT_FILENO, nix::libc::TIOCGWINSZ, &mut size) } {
+        0 => size.ws_col as usize,
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_columns() {
+        let items = ["a", "bb", "ccc", "d", "eeeee"];
+
+        assert_eq!(format_columns(&items, 80), "a  bb  ccc  d  eeeee\n");
+        assert_eq!(format_columns(&items, 14), "a   ccc  eeeee\nbb  d\n");
+        assert_eq!(format_columns(&items, 10), "a    d\nbb   eeeee\nccc\n");
+        assert_eq!(format_columns(&items, 5), "a\nbb\nccc\nd\neeeee\n");
+        assert_eq!(format_columns(&items, 3), "a\nbb\nccc\nd\neeeee\n");
+    }
+
+    #[test]
+    fn test_format_columns_edge_cases() {
+        assert_eq!(format_columns(&[] as &[&str], 80), "");
+        assert_eq!(format_columns(&["only"], 2), "only\n");
+        assert_eq!(format_columns(&["é", "ü"], 4), "é  ü\n");
+    }
+}
@@ -0,0 +1,275 @@
+use std::collections::{HashMap, HashSet};
+use std::ffi::CString;
+use std::os::unix::fs::MetadataExt;
+
+/// The shell's variables, of which only the exported ones reach the programs it starts. It starts
+/// out as the environment rush itself was started with, all of it exported.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Env {
+    vars: HashMap<String, String>,
+    /// Names of the exported variables, which may not be set yet.
+    exported: HashSet<String>,
+}
+
+impl Env {
+    /// The environment of the rush process.
+    pub fn from_process() -> Self {
+        let vars = std::env::vars_os()
+            .filter_map(|(name, value)| Some((name.into_string().ok()?, value.into_string().ok()?)))
+            .collect::<HashMap<_, _>>();
+        let exported = vars.keys().cloned().collect();
+        Self { vars, exported }
+    }
+
+    /// Makes sure `PWD` names the working directory, replacing it when it doesn't: whoever started
+    /// rush may have left a stale one behind. A `PWD` reached through symlinks is kept.
+    pub fn sync_pwd(&mut self) {
+        let Ok(cwd) = std::env::current_dir() else { return };
+        let is_cwd = |pwd: &str| {
+            let (Ok(pwd), Ok(cwd)) = (std::fs::metadata(pwd), std::fs::metadata(".")) else {
+                return false;
+            };
+            (pwd.dev(), pwd.ino()) == (cwd.dev(), cwd.ino())
+        };
+
+        if !self.get("PWD").is_some_and(|pwd| pwd.starts_with('/') && is_cwd(pwd)) {
+            self.set("PWD", cwd.to_string_lossy());
+        }
+        self.export("PWD");
+    }
+
+    /// Counts one more shell in `SHLVL`, treating a missing or garbled value, or one too large to
+    /// count any higher, as no shell at all.
+    pub fn increment_shlvl(&mut self) {
+        let level = self
+            .get("SHLVL")
+            .and_then(|level| level.parse::<u32>().ok())
+            .and_then(|level| level.checked_add(1))
+            .unwrap_or(1);
+        self.set("SHLVL", level.to_string());
+        self.export("SHLVL");
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.vars.get(name).map(String::as_str)
+    }
+
+    /// Sets a variable, which stays exported if it was, and otherwise belongs to the shell alone
+    /// until it is exported.
+    pub fn set(&mut self, name: impl Into<String>, value: impl Into<String>) {
+        self.vars.insert(name.into(), value.into());
+    }
+
+    /// Passes the variable `name` on to the programs the shell starts, from now on or from when it
+    /// is set.
+    pub fn export(&mut self, name: impl Into<String>) {
+        self.exported.insert(name.into());
+    }
+
+    pub fn is_exported(&self, name: &str) -> bool {
+        self.exported.contains(name)
+    }
+
+    /// Removes a variable, along with its export.
+    pub fn remove(&mut self, name: &str) -> Option<String> {
+        self.exported.remove(name);
+        self.vars.remove(name)
+    }
+
+    /// Every variable, exported or not, sorted by name.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        let mut vars = self.vars.iter().collect::<Vec<_>>();
+        vars.sort_unstable();
+        vars.into_iter().map(|(name, value)| (name.as_str(), value.as_str()))
+    }
+
+    /// `NAME=value` strings for exec out of the exported variables, with `overrides` (a command's
+    /// prefix assignments) taking precedence over their values.
+    pub(crate) fn to_cstrings<'a>(&self, overrides: impl IntoIterator<Item = (&'a str, String)>) -> Vec<CString> {
+        let mut vars = self
+            .vars
+            .iter()
+            .filter(|(name, _)| self.is_exported(name))
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect::<HashMap<_, _>>();
+        vars.extend(overrides.into_iter().map(|(name, value)| (name.to_string(), value)));
+
+        // a value can't hold a NUL byte once it is in the environment block, so it is left out
+        vars.into_iter()
+            .filter_map(|(name, value)| CString::new(format!("{name}={value}")).ok())
+            .collect()
+    }
+}
+
+/// `export NAME[=value]...` exports variables, setting the ones given a value, `export` alone lists
+/// the exported ones in a form that can be read back as input.
+pub(crate) fn export(env: &mut Env, args: &[&str]) -> i32 {
+    let (options, args) = crate::builtins::split_options(args);
+    if let Some(option) = options.first() {
+        eprintln!("rush: export: {option}: invalid option");
+        return 2;
+    }
+
+    if args.is_empty() {
+        env.iter()
+            .filter(|(name, _)| env.is_exported(name))
+            .for_each(|(name, value)| println!("export {name}={}", quote(value)));
+        return 0;
+    }
+
+    let mut status = 0;
+    for arg in args {
+        let (name, value) = match arg.split_once('=') {
+            Some((name, value)) => (name, Some(value)),
+            None => (*arg, None),
+        };
+        if !is_name(name) {
+            eprintln!("rush: export: `{arg}': not a valid identifier");
+            status = 1;
+            continue;
+        }
+
+        if let Some(value) = value {
+            env.set(name, value);
+        }
+        env.export(name);
+    }
+
+    status
+}
+
+/// `unset NAME...` removes variables, names that aren't set are ignored.
+pub(crate) fn unset(env: &mut Env, args: &[&str]) -> i32 {
+    let (options, args) = crate::builtins::split_options(args);
+    if let Some(option) = options.first() {
+        eprintln!("rush: unset: {option}: invalid option");
+        return 2;
+    }
+
+    let mut status = 0;
+    for name in args {
+        if !is_name(name) {
+            eprintln!("rush: unset: `{name}': not a valid identifier");
+            status = 1;
+            continue;
+        }
+        env.remove(name);
+    }
+
+    status
+}
+
+fn is_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Single-quotes `value`, closing the quotes around each `'` it contains.
+fn quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_export_and_unset() {
+        let mut env = Env::default();
+
+        assert_eq!(export(&mut env, &["FOO=bar", "EMPTY=", "X=a=b"]), 0);
+        assert_eq!(env.get("FOO"), Some("bar"));
+        assert_eq!(env.get("EMPTY"), Some(""));
+        assert_eq!(env.get("X"), Some("a=b"));
+
+        assert_eq!(export(&mut env, &["1FOO=bar", "OK=1"]), 1);
+        assert_eq!(env.get("OK"), Some("1"));
+
+        env.set("SHELL_ONLY", "1");
+        assert!(!env.is_exported("SHELL_ONLY"));
+        assert_eq!(export(&mut env, &["SHELL_ONLY"]), 0);
+        assert!(env.is_exported("SHELL_ONLY"));
+        assert_eq!(env.get("SHELL_ONLY"), Some("1"));
+
+        assert_eq!(unset(&mut env, &["FOO", "MISSING"]), 0);
+        assert_eq!(env.get("FOO"), None);
+        env.set("FOO", "again");
+        assert!(!env.is_exported("FOO"));
+        assert_eq!(unset(&mut env, &["A-B"]), 1);
+    }
+
+    #[test]
+    fn test_export_end_of_options() {
+        let mut env = Env::default();
+
+        assert_eq!(export(&mut env, &["--", "FOO=bar"]), 0);
+        assert_eq!(env.get("FOO"), Some("bar"));
+        assert_eq!(export(&mut env, &["-x", "OTHER=1"]), 2);
+        assert_eq!(env.get("OTHER"), None);
+    }
+
+    #[test]
+    fn test_unset_end_of_options() {
+        let mut env = Env::default();
+        env.set("FOO", "bar");
+
+        assert_eq!(unset(&mut env, &["-x", "FOO"]), 2);
+        assert_eq!(env.get("FOO"), Some("bar"));
+        assert_eq!(unset(&mut env, &["--", "FOO"]), 0);
+        assert_eq!(env.get("FOO"), None);
+    }
+
+    #[test]
+    fn test_sync_pwd() {
+        let cwd = std::env::current_dir().unwrap().display().to_string();
+        let mut env = Env::default();
+
+        env.set("PWD", "/nonexistent");
+        env.sync_pwd();
+        assert_eq!(env.get("PWD"), Some(cwd.as_str()));
+
+        env.set("PWD", std::env::temp_dir().display().to_string());
+        env.sync_pwd();
+        assert_eq!(env.get("PWD"), Some(cwd.as_str()));
+
+        env.remove("PWD");
+        env.sync_pwd();
+        assert_eq!(env.get("PWD"), Some(cwd.as_str()));
+    }
+
+    #[test]
+    fn test_increment_shlvl() {
+        let mut env = Env::default();
+        env.increment_shlvl();
+        assert_eq!(env.get("SHLVL"), Some("1"));
+        env.increment_shlvl();
+        assert_eq!(env.get("SHLVL"), Some("2"));
+
+        env.set("SHLVL", "-3");
+        env.increment_shlvl();
+        assert_eq!(env.get("SHLVL"), Some("1"));
+
+        env.set("SHLVL", "4294967295");
+        env.increment_shlvl();
+        assert_eq!(env.get("SHLVL"), Some("1"));
+        env.set("SHLVL", "4294967296");
+        env.increment_shlvl();
+        assert_eq!(env.get("SHLVL"), Some("1"));
+    }
+
+    #[test]
+    fn test_to_cstrings() {
+        let mut env = Env::default();
+        env.set("FOO", "bar");
+        env.set("KEEP", "it's");
+        env.set("SHELL_ONLY", "1");
+        env.export("FOO");
+        env.export("KEEP");
+
+        let mut vars = env.to_cstrings([("FOO", String::from("baz")), ("PREFIX", String::from("2"))]);
+        vars.sort();
+        assert_eq!(vars, [c"FOO=baz", c"KEEP=it's", c"PREFIX=2"]);
+        assert_eq!(quote("it's"), r"'it'\''s'");
+    }
+}
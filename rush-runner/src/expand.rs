@@ -0,0 +1,454 @@
+use std::io::{Read, Write};
+use std::os::fd::AsRawFd;
+
+use nix::fcntl::OFlag;
+use nix::sys::wait::WaitStatus;
+use nix::unistd::ForkResult;
+use rush_parser::Word;
+
+use crate::result::Result;
+use crate::{ExecCtx, redirect};
+
+/// The fields a word expands into. Single-quoted words are taken literally, anything else has
+/// each `$NAME`, `${NAME}` and special parameter replaced by its value, or by nothing when it isn't
+/// set, `${!ref}` by the value of the variable `$ref` names, `${!prefix*}` by the names of the
+/// variables starting with `prefix`, and each `$(...)` by the output of the command. Unquoted
+/// words also get a leading `~` expanded and the quotes of their quoted parts removed, and are
+/// split on the whitespace command substitutions put in them outside of those parts. Also returns
+/// the exit status of the last command substitution in the word, if it has any.
+pub(crate) fn expand_word(ctx: &ExecCtx<'_>, word: &Word) -> (Vec<String>, Option<i32>) {
+    expand(ctx, word, true)
+}
+
+/// A word expanded into a single string, as assignment values are, whose expansions aren't split.
+pub(crate) fn expand_value(ctx: &ExecCtx<'_>, word: &Word) -> (String, Option<i32>) {
+    let (fields, status) = expand(ctx, word, false);
+    (fields.concat(), status)
+}
+
+fn expand(ctx: &ExecCtx<'_>, word: &Word, split: bool) -> (Vec<String>, Option<i32>) {
+    if let Some(name) = word.variable_name(ctx.source) {
+        return (vec![lookup(ctx, name)], None);
+    }
+
+    let value = word.value(ctx.source);
+    let mut fields = Fields::new(word.quoted || !split);
+    match word.quote(ctx.source) {
+        Some('\'') => fields.push_str(value),
+        Some(_) => expand_text(ctx, value, false, true, &mut fields),
+        None => {
+            let rest = match expand_tilde(ctx, value) {
+                Some((home, rest)) => {
+                    fields.push_str(&home);
+                    rest
+                }
+                None => value,
+            };
+            expand_unquoted(ctx, rest, split, &mut fields);
+        }
+    }
+
+    fields.finish()
+}
+
+/// Fields a word is expanded into, built one piece of text at a time.
+struct Fields {
+    fields: Vec<String>,
+    /// Whether the last field holds anything, even if only an empty expansion. A field that was
+    /// never started, such as the one an unquoted `$(true)` leaves, is dropped.
+    started: bool,
+    /// Exit status of the last command substitution expanded into the fields.
+    status: Option<i32>,
+}
+
+impl Fields {
+    fn new(started: bool) -> Self {
+        Self {
+            fields: vec![String::new()],
+            started,
+            status: None,
+        }
+    }
+
+    /// Starts the field even if nothing is added to it, as an empty quoted part does.
+    fn start(&mut self) {
+        self.started = true;
+    }
+
+    fn push_str(&mut self, text: &str) {
+        if !text.is_empty() {
+            self.fields.last_mut().expect("there always is a field").push_str(text);
+            self.started = true;
+        }
+    }
+
+    /// Adds text split on whitespace, each run of it ending the field being built.
+    fn push_split(&mut self, text: &str) {
+        for (idx, piece) in text.split(char::is_whitespace).enumerate() {
+            if idx > 0 && self.started {
+                self.fields.push(String::new());
+                self.started = false;
+            }
+            if !piece.is_empty() {
+                self.push_str(piece);
+            }
+        }
+    }
+
+    fn finish(mut self) -> (Vec<String>, Option<i32>) {
+        if !self.started {
+            self.fields.pop();
+        }
+        (self.fields, self.status)
+    }
+}
+
+/// Expands the `~` or `~user` prefix a word starts with, up to its first `/`, into a home
+/// directory. Returns the home directory and the rest of the word, or `None` when the word has no
+/// such prefix or the user doesn't exist, which leaves it as it is.
+fn expand_tilde<'a>(ctx: &ExecCtx<'_>, word: &'a str) -> Option<(String, &'a str)> {
+    let word = word.strip_prefix('~')?;
+    let (user, rest) = word.split_at(word.find('/').unwrap_or(word.len()));
+
+    let home = match user {
+        "" => ctx.env.lock().unwrap().get("HOME").map(str::to_string),
+        user => nix::unistd::User::from_name(user)
+            .ok()
+            .flatten()
+            .map(|user| user.dir.to_string_lossy().into_owned()),
+    }?;
+    Some((home, rest))
+}
+
+/// Expands an unquoted word, whose quoted parts, as in `foo"bar baz"`, are expanded as they would
+/// be in a quoted word of their own and never split. The quotes themselves are removed, as is the
+/// backslash of a `\"` or `\'` outside of them.
+fn expand_unquoted(ctx: &ExecCtx<'_>, text: &str, split: bool, fields: &mut Fields) {
+    let mut rest = text;
+
+    while let Some((idx, quote)) = find_quote(rest) {
+        expand_text(ctx, &rest[..idx], split, false, fields);
+        let after = &rest[idx + 1..];
+
+        if quote == '\\' {
+            // the escaped quote is a single byte
+            fields.push_str(&after[..1]);
+            rest = &after[1..];
+            continue;
+        }
+
+        let closing = closing_quote(after, quote).unwrap_or(after.len());
+        fields.start();
+        match quote {
+            '\'' => fields.push_str(&after[..closing]),
+            _ => expand_text(ctx, &after[..closing], false, true, fields),
+        }
+        rest = after.get(closing + 1..).unwrap_or_default();
+    }
+
+    expand_text(ctx, rest, split, false, fields);
+}
+
+/// The first quote opening a quoted part of unquoted text, or backslash escaping a quote, with its
+/// position. Command substitutions are skipped over, they keep their quotes.
+fn find_quote(text: &str) -> Option<(usize, char)> {
+    let mut idx = 0;
+    while let Some(ch) = text[idx..].chars().next() {
+        match ch {
+            '\'' | '"' => return Some((idx, ch)),
+            '\\' if text[idx + 1..].starts_with(['\'', '"']) => return Some((idx, ch)),
+            '$' if text[idx + 1..].starts_with('(') => {
+                let group = &text[idx + 1..];
+                idx += 1 + rush_lexer::command_substitution_len(group).unwrap_or(group.len());
+                continue;
+            }
+            _ => {}
+        }
+        idx += ch.len_utf8();
+    }
+    None
+}
+
+/// Where the quoted part `text` starts with ends, at its closing `quote`. Inside double quotes a
+/// backslash escapes the next character.
+fn closing_quote(text: &str, quote: char) -> Option<usize> {
+    let mut chars = text.char_indices();
+    while let Some((idx, ch)) = chars.next() {
+        match ch {
+            '\\' if quote == '"' => _ = chars.next(),
+            ch if ch == quote => return Some(idx),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Expands the parameters and command substitutions in `text`. In double-quoted text a backslash
+/// only escapes `"`, `\`, `$` and `` ` ``, and is removed; anywhere else only `\$` is a literal
+/// dollar sign.
+fn expand_text(ctx: &ExecCtx<'_>, text: &str, split: bool, double_quoted: bool, fields: &mut Fields) {
+    let mut rest = text;
+
+    while let Some(special) = rest.find(|ch| ch == '$' || (double_quoted && ch == '\\')) {
+        if double_quoted && rest[special..].starts_with('\\') {
+            fields.push_str(&rest[..special]);
+            let escaped = &rest[special + 1..];
+            match escaped.chars().next() {
+                Some(ch @ ('"' | '\\' | '$' | '`')) => {
+                    fields.push_str(&escaped[..ch.len_utf8()]);
+                    rest = &escaped[ch.len_utf8()..];
+                }
+                _ => {
+                    fields.push_str("\\");
+                    rest = escaped;
+                }
+            }
+            continue;
+        }
+
+        let dollar = special;
+        // `\$` is a literal dollar sign
+        if rest[..dollar].ends_with('\\') {
+            fields.push_str(&rest[..dollar - 1]);
+            fields.push_str("$");
+            rest = &rest[dollar + 1..];
+            continue;
+        }
+
+        fields.push_str(&rest[..dollar]);
+        let after = &rest[dollar + 1..];
+
+        if after.starts_with('(')
+            && let Some(len) = rush_lexer::command_substitution_len(after)
+        {
+            let (output, status) = command_output(ctx, &after[1..len - 1]);
+            fields.status = Some(status);
+            if split {
+                fields.push_split(&output);
+            } else {
+                fields.push_str(&output);
+            }
+            rest = &after[len..];
+            continue;
+        }
+
+        let (name, consumed) = match after.chars().next() {
+            Some('{') => match after.find('}') {
+                Some(close) => (&after[1..close], close + 1),
+                None => ("", 0),
+            },
+            Some('?' | '$' | '!') => (&after[..1], 1),
+            _ => {
+                let len = after
+                    .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                    .unwrap_or(after.len());
+                (&after[..len], len)
+            }
+        };
+
+        // a `$` that doesn't start a parameter stays as it is
+        if name.is_empty() {
+            fields.push_str("$");
+        } else if let Some(prefix) = name.strip_prefix('!').and_then(|name| name.strip_suffix(['*', '@'])) {
+            // `${!prefix*}` lists the variables whose names start with `prefix`, one field each
+            let names = variable_names(ctx, prefix).join(" ");
+            if split {
+                fields.push_split(&names);
+            } else {
+                fields.push_str(&names);
+            }
+        } else {
+            fields.push_str(&lookup(ctx, name));
+        }
+        rest = &after[consumed..];
+    }
+
+    fields.push_str(rest);
+}
+
+/// Runs `command` in a forked copy of the shell and returns what it printed, without its trailing
+/// newlines, and its exit status. Whatever it changes in the shell's state stays in the copy.
+fn command_output(ctx: &ExecCtx<'_>, command: &str) -> (String, i32) {
+    let result = (|| -> Result<(String, i32)> {
+        let (read, write) = nix::unistd::pipe2(OFlag::O_CLOEXEC)?;
+
+        match unsafe { nix::unistd::fork() }? {
+            ForkResult::Child => {
+                crate::reset_signals_for_child();
+                drop(read);
+                if redirect::duplicate(write.as_raw_fd(), nix::libc::STDOUT_FILENO).is_err() {
+                    std::process::exit(1);
+                }
+                drop(write);
+
+                // the copy of the shell has no terminal to hand out to jobs
+                ctx.options.lock().unwrap().monitor = false;
+                let status = crate::execute_eval(ctx, command).unwrap_or_else(|e| {
+                    eprintln!("rush: {e}");
+                    1
+                });
+                _ = std::io::stdout().flush();
+                std::process::exit(status);
+            }
+            ForkResult::Parent { child } => {
+                drop(write);
+                // whatever was read before a failed read is still the command's output
+                let mut output = vec![];
+                _ = std::fs::File::from(read).read_to_end(&mut output);
+                let status = match crate::waitpid_eintr(child, None)? {
+                    WaitStatus::Exited(_, exit_code) => exit_code,
+                    WaitStatus::Signaled(_, signal, _) => 128 + signal as i32,
+                    _ => 1,
+                };
+
+                let output = String::from_utf8_lossy(&output);
+                Ok((output.trim_end_matches('\n').to_string(), status))
+            }
+        }
+    })();
+
+    result.unwrap_or_else(|e| {
+        eprintln!("rush: command substitution: {e}");
+        (String::new(), 1)
+    })
+}
+
+fn lookup(ctx: &ExecCtx<'_>, name: &str) -> String {
+    // `${!ref}` is the value of the variable `$ref` names; a name that is itself indirect isn't
+    // followed any further
+    if let Some(reference) = name.strip_prefix('!').filter(|reference| !reference.is_empty()) {
+        return match lookup(ctx, reference) {
+            target if target.is_empty() || target.starts_with('!') => String::new(),
+            target => lookup(ctx, &target),
+        };
+    }
+
+    ctx.special_parameter(name)
+        .or_else(|| ctx.env.lock().unwrap().get(name).map(str::to_string))
+        .unwrap_or_default()
+}
+
+fn variable_names(ctx: &ExecCtx<'_>, prefix: &str) -> Vec<String> {
+    ctx.env
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|(name, _)| name.starts_with(prefix))
+        .map(|(name, _)| name.to_string())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ShellOptions;
+    use crate::tests::run;
+
+    fn expand<'a>(ctx: &ExecCtx<'a>, source: &'a str) -> Vec<String> {
+        let ast = rush_parser::Parser::new()
+            .parse(rush_lexer::Lexer::new(source).lex())
+            .unwrap();
+        let command = ast.into_command().unwrap();
+        let ctx = ctx.with_source(source);
+        command.args.iter().flat_map(|arg| expand_word(&ctx, arg).0).collect()
+    }
+
+    #[test]
+    fn test_expand_word() {
+        let ctx = run("true", ShellOptions::new(false));
+        ctx.env.lock().unwrap().set("HOME", "/home/rush");
+        ctx.env.lock().unwrap().set("USER", "rush");
+        ctx.env.lock().unwrap().remove("UNSET");
+
+        assert_eq!(expand(&ctx, "echo $HOME ${USER}"), ["/home/rush", "rush"]);
+        assert_eq!(
+            expand(&ctx, r#"echo "${USER}x" "$USER/$HOME" "a\$USER""#),
+            ["rushx", "rush//home/rush", "a$USER"]
+        );
+        assert_eq!(expand(&ctx, "echo '$USER' '${USER}'"), ["$USER", "${USER}"]);
+        assert_eq!(
+            expand(&ctx, r#"echo $UNSET "[$UNSET]" "$" "${" "$?""#),
+            ["", "[]", "$", "${", "0"]
+        );
+        assert_eq!(expand(&ctx, "echo pre$USER ${USER}post"), ["prerush", "rushpost"]);
+    }
+
+    #[test]
+    fn test_expand_quotes() {
+        let ctx = run("true", ShellOptions::new(false));
+        ctx.env.lock().unwrap().set("USER", "rush");
+
+        assert_eq!(
+            expand(&ctx, r#"echo "a \"b\" c" "a\\b" "\$USER" "\`" "a\qb""#),
+            [r#"a "b" c"#, r"a\b", "$USER", "`", r"a\qb"]
+        );
+        assert_eq!(expand(&ctx, r#"echo foo"bar baz""#), ["foobar baz"]);
+        assert_eq!(
+            expand(&ctx, r#"echo 'a'$USER"$USER x" a\"b "a"b '' x""y"#),
+            ["arushrush x", "a\"b", "ab", "", "xy"]
+        );
+        assert_eq!(
+            expand(&ctx, r#"echo $(echo "a b")"c d" "$(echo 'x')""#),
+            ["a", "bc d", "x"]
+        );
+    }
+
+    #[test]
+    fn test_expand_tilde() {
+        let ctx = run("true", ShellOptions::new(false));
+        ctx.env.lock().unwrap().set("HOME", "/home/rush");
+        ctx.env.lock().unwrap().set("USER", "rush");
+
+        assert_eq!(
+            expand(&ctx, "echo ~ ~/foo ~/$USER"),
+            ["/home/rush", "/home/rush/foo", "/home/rush/rush"]
+        );
+        assert_eq!(
+            expand(&ctx, "echo a~b '~' \"~/foo\" ~nouser ~nouser/foo"),
+            ["a~b", "~", "~/foo", "~nouser", "~nouser/foo"]
+        );
+        let root = nix::unistd::User::from_name("root").unwrap().unwrap().dir;
+        let root = root.to_str().unwrap();
+        assert_eq!(
+            expand(&ctx, "echo ~root ~root/foo"),
+            [root.to_string(), format!("{root}/foo")]
+        );
+    }
+
+    #[test]
+    fn test_command_substitution() {
+        let ctx = run("true", ShellOptions::new(false));
+        ctx.env.lock().unwrap().set("USER", "rush");
+
+        assert_eq!(expand(&ctx, "echo $(echo hi)"), ["hi"]);
+        assert_eq!(expand(&ctx, "echo $(printf 'a\\n\\n\\n')"), ["a"]);
+        assert_eq!(expand(&ctx, "echo x$(echo a  b)y $(true) z"), ["xa", "by", "z"]);
+        assert_eq!(expand(&ctx, r#"echo "$(echo 'a  b')" "$(true)""#), ["a  b", ""]);
+        assert_eq!(expand(&ctx, "echo $(echo $(echo $USER))"), ["rush"]);
+        assert_eq!(expand(&ctx, "echo $(echo ')') '$(echo hi)'"), [")", "$(echo hi)"]);
+    }
+
+    #[test]
+    fn test_indirect_expansion() {
+        let ctx = run("true", ShellOptions::new(false));
+        {
+            let mut env = ctx.env.lock().unwrap();
+            env.set("TARGET", "value");
+            env.set("REF", "TARGET");
+            env.set("LOOP", "!LOOP");
+            env.set("RUSH_TEST_A", "1");
+            env.set("RUSH_TEST_B", "2");
+        }
+
+        assert_eq!(
+            expand(&ctx, r#"echo ${!REF} "${!REF}x" ${!UNSET}x ${!LOOP}x"#),
+            ["value", "valuex", "x", "x"]
+        );
+        assert_eq!(expand(&ctx, "echo ${!RUSH_TEST_*}"), ["RUSH_TEST_A", "RUSH_TEST_B"]);
+        assert_eq!(
+            expand(&ctx, r#"echo "${!RUSH_TEST_@}" ${!RUSH_NONE*}"#),
+            ["RUSH_TEST_A RUSH_TEST_B"]
+        );
+        assert_eq!(expand(&ctx, "echo ${!}x"), ["x"]);
+    }
+}
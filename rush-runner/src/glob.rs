@@ -0,0 +1,176 @@
+use std::path::Path;
+
+/// Filename expansion of `pattern`, whose components can use `*`, `?` and `[...]`: the paths
+/// matching it, sorted, or `None` when it has no such characters or nothing matches, in which case
+/// the pattern is used as it is. Hidden files only match a component that starts with `.` itself.
+pub(crate) fn glob_expand(pattern: &str) -> Option<Vec<String>> {
+    if !has_metachars(pattern) {
+        return None;
+    }
+
+    let mut paths = vec![String::from(if pattern.starts_with('/') { "/" } else { "" })];
+    let components = pattern
+        .split('/')
+        .filter(|component| !component.is_empty())
+        .collect::<Vec<_>>();
+
+    for (idx, component) in components.iter().enumerate() {
+        // every component but the last one has to lead into a directory
+        let is_last = idx + 1 == components.len() && !pattern.ends_with('/');
+        let keep =
+            |path: &str| if is_last { Path::new(path).symlink_metadata().is_ok() } else { Path::new(path).is_dir() };
+
+        paths = if has_metachars(component) {
+            let pattern = component.chars().collect::<Vec<_>>();
+            paths
+                .iter()
+                .flat_map(|base| {
+                    let dir = if base.is_empty() { "." } else { base.as_str() };
+                    let mut names = std::fs::read_dir(dir)
+                        .into_iter()
+                        .flatten()
+                        .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+                        .filter(|name| !name.starts_with('.') || component.starts_with('.'))
+                        .filter(|name| matches(&pattern, &name.chars().collect::<Vec<_>>()))
+                        .collect::<Vec<_>>();
+                    names.sort_unstable();
+                    names.into_iter().map(move |name| join(base, &name))
+                })
+                .filter(|path| keep(path))
+                .collect()
+        } else {
+            paths
+                .iter()
+                .map(|base| join(base, component))
+                .filter(|path| keep(path))
+                .collect()
+        };
+    }
+
+    if pattern.ends_with('/') {
+        paths.iter_mut().for_each(|path| path.push('/'));
+    }
+    (!paths.is_empty()).then_some(paths)
+}
+
+fn has_metachars(pattern: &str) -> bool {
+    pattern.contains(['*', '?', '['])
+}
+
+fn join(base: &str, name: &str) -> String {
+    match base {
+        "" => name.to_string(),
+        base if base.ends_with('/') => format!("{base}{name}"),
+        base => format!("{base}/{name}"),
+    }
+}
+
+/// Whether `name` matches the whole of `pattern`.
+fn matches(pattern: &[char], name: &[char]) -> bool {
+    match pattern.split_first() {
+        None => name.is_empty(),
+        Some(('*', rest)) => (0..=name.len()).any(|skip| matches(rest, &name[skip..])),
+        Some(('?', rest)) => !name.is_empty() && matches(rest, &name[1..]),
+        Some(('[', rest)) => match (bracket(rest, name.first().copied()), name.split_first()) {
+            (Some((matched, rest)), Some((_, name))) => matched && matches(rest, name),
+            (Some(_), None) => false,
+            // without a closing `]` the bracket is an ordinary character
+            (None, _) => name.first() == Some(&'[') && matches(rest, &name[1..]),
+        },
+        Some((c, rest)) => name.first() == Some(c) && matches(rest, &name[1..]),
+    }
+}
+
+/// Matches `c` against the bracket expression `pattern` starts with, just past its `[`. Returns
+/// whether it matched and the pattern after the closing `]`, or `None` if there is no `]`.
+fn bracket(pattern: &[char], c: Option<char>) -> Option<(bool, &[char])> {
+    let (negated, mut rest) = match pattern.split_first() {
+        Some(('!' | '^', rest)) => (true, rest),
+        _ => (false, pattern),
+    };
+
+    let mut matched = false;
+    let mut first = true;
+    loop {
+        match rest {
+            // a `]` right after the opening bracket is a member, not the end
+            [']', after @ ..] if !first => return Some((matched != negated, after)),
+            [start, '-', end, after @ ..] if *end != ']' => {
+                matched |= c.is_some_and(|c| (*start..=*end).contains(&c));
+                rest = after;
+            }
+            [member, after @ ..] => {
+                matched |= c == Some(*member);
+                rest = after;
+            }
+            [] => return None,
+        }
+        first = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::test_dir;
+
+    fn matches(pattern: &str, name: &str) -> bool {
+        super::matches(&pattern.chars().collect::<Vec<_>>(), &name.chars().collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn test_matches() {
+        assert!(matches("*.txt", "a.txt"));
+        assert!(matches("*.txt", ".txt"));
+        assert!(!matches("*.txt", "a.txt.bak"));
+        assert!(matches("file?.log", "file1.log"));
+        assert!(!matches("file?.log", "file.log"));
+        assert!(matches("[ab].c", "b.c"));
+        assert!(!matches("[ab].c", "c.c"));
+        assert!(matches("[a-c]x", "bx"));
+        assert!(matches("[!a-c]x", "dx"));
+        assert!(!matches("[^a-c]x", "ax"));
+        assert!(matches("[]]", "]"));
+        assert!(matches("[a-]", "-"));
+        assert!(matches("[ab", "[ab"));
+    }
+
+    #[test]
+    fn test_glob_expand() {
+        let dir = test_dir("glob");
+        for file in [
+            "a.txt",
+            "b.txt",
+            ".hidden.txt",
+            "file1.log",
+            "file22.log",
+            "a.c",
+            "b.c",
+            "c.c",
+        ] {
+            std::fs::write(dir.join(file), "").unwrap();
+        }
+        std::fs::create_dir_all(dir.join("sub")).unwrap();
+        std::fs::write(dir.join("sub").join("x.txt"), "").unwrap();
+
+        let glob = |pattern: &str| {
+            let paths = glob_expand(&format!("{}/{pattern}", dir.display()))?;
+            let prefix = format!("{}/", dir.display());
+            Some(
+                paths
+                    .iter()
+                    .map(|path| path.strip_prefix(&prefix).unwrap().to_string())
+                    .collect::<Vec<_>>(),
+            )
+        };
+
+        assert_eq!(glob("*.txt").unwrap(), ["a.txt", "b.txt"]);
+        assert_eq!(glob(".*.txt").unwrap(), [".hidden.txt"]);
+        assert_eq!(glob("file?.log").unwrap(), ["file1.log"]);
+        assert_eq!(glob("[ab].c").unwrap(), ["a.c", "b.c"]);
+        assert_eq!(glob("*/*.txt").unwrap(), ["sub/x.txt"]);
+        assert_eq!(glob("s*/").unwrap(), ["sub/"]);
+        assert_eq!(glob("*.md"), None);
+        assert_eq!(glob("a.txt"), None);
+    }
+}
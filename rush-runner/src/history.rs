@@ -0,0 +1,210 @@
+use std::io::Write;
+use std::path::PathBuf;
+
+use crate::Env;
+
+/// Commands entered at the prompt, kept in memory and appended to a history file as they are read
+/// so the history outlives the shell.
+#[derive(Debug, Default)]
+pub struct History {
+    entries: Vec<String>,
+    path: Option<PathBuf>,
+}
+
+impl History {
+    /// The history stored at `path`, each line of the file an entry with its newlines escaped, see
+    /// [`History::push`]. A missing or unreadable file is an empty history.
+    pub fn load(path: Option<PathBuf>) -> Self {
+        let entries = path
+            .as_ref()
+            .and_then(|path| std::fs::read(path).ok())
+            .map(|contents| {
+                String::from_utf8_lossy(&contents)
+                    .lines()
+                    .filter(|line| !line.trim().is_empty())
+                    .map(unescape)
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Self { entries, path }
+    }
+
+    /// `$RUSH_HISTFILE`, or `~/.rush_history` when it isn't set.
+    pub fn default_path(env: &Env) -> Option<PathBuf> {
+        match env.get("RUSH_HISTFILE") {
+            Some(path) => Some(PathBuf::from(path)),
+            None => env.get("HOME").map(|home| PathBuf::from(home).join(".rush_history")),
+        }
+    }
+
+    /// Records `command` unless it is blank or repeats the previous entry. A command spanning lines,
+    /// like a quote continued at the prompt, is written to the file as a single line, with its
+    /// newlines as `\n` and its backslashes as `\\`.
+    pub fn push(&mut self, command: &str) {
+        let command = command.trim_end_matches(['\n', '\r']);
+        if command.trim().is_empty() || self.entries.last().is_some_and(|last| last == command) {
+            return;
+        }
+
+        if let Some(path) = &self.path {
+            let appended = std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .and_then(|mut file| writeln!(file, "{}", escape(command)));
+            if let Err(e) = appended {
+                eprintln!("rush: history: {}: {e}", path.display());
+            }
+        }
+
+        self.entries.push(command.to_string());
+    }
+
+    pub fn entries(&self) -> &[String] {
+        &self.entries
+    }
+}
+
+/// An entry as a line of the history file.
+fn escape(command: &str) -> String {
+    command.replace('\\', "\\\\").replace('\n', "\\n")
+}
+
+/// An entry from a line of the history file, a backslash escaping anything else is kept as it is.
+fn unescape(line: &str) -> String {
+    let mut command = String::with_capacity(line.len());
+    let mut chars = line.chars().peekable();
+    while let Some(ch) = chars.next() {
+        let unescaped = match (ch, chars.peek()) {
+            ('\\', Some('n')) => '\n',
+            ('\\', Some('\\')) => '\\',
+            _ => {
+                command.push(ch);
+                continue;
+            }
+        };
+        chars.next();
+        command.push(unescaped);
+    }
+    command
+}
+
+/// `history [N]` lists the commands entered so far, or only the last `N`, numbered from the first.
+/// The continuation lines of a multi-line command are indented to line up under its first line.
+pub(crate) fn history(history: &History, args: &[&str]) -> i32 {
+    let (options, args) = crate::builtins::split_options(args);
+    if let Some(option) = options.first() {
+        eprintln!("rush: history: {option}: invalid option");
+        return 2;
+    }
+
+    let count = match args {
+        [] => history.entries.len(),
+        [count] => match count.parse::<usize>() {
+            Ok(count) => count,
+            Err(_) => {
+                eprintln!("rush: history: {count}: numeric argument required");
+                return 1;
+            }
+        },
+        _ => {
+            eprintln!("rush: history: too many arguments");
+            return 1;
+        }
+    };
+
+    let mut stdout = std::io::stdout();
+    let skipped = history.entries.len().saturating_sub(count);
+    for (idx, command) in history.entries.iter().enumerate().skip(skipped) {
+        let command = command.replace('\n', "\n       ");
+        if let Err(e) = writeln!(stdout, "{:>5}  {command}", idx + 1) {
+            eprintln!("rush: history: write error: {e}");
+            return 1;
+        }
+    }
+
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ShellOptions;
+    use crate::tests::{run, run_in, test_dir};
+
+    #[test]
+    fn test_history_file() {
+        let path = test_dir("history").join("history");
+        _ = std::fs::remove_file(&path);
+
+        let mut history = History::load(Some(path.clone()));
+        history.push("echo hi\n");
+        history.push("echo hi\n");
+        history.push("  \n");
+        history.push("ls -la\n");
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "echo hi\nls -la\n");
+
+        let history = History::load(Some(path.clone()));
+        assert_eq!(history.entries(), ["echo hi", "ls -la"]);
+    }
+
+    #[test]
+    fn test_multiline_entries() {
+        let path = test_dir("history").join("multiline");
+        _ = std::fs::remove_file(&path);
+
+        let mut history = History::load(Some(path.clone()));
+        history.push("echo \"a\nb\"\n");
+        history.push("echo a\\nb \\\n");
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "echo \"a\\nb\"\necho a\\\\nb \\\\\n"
+        );
+
+        let history = History::load(Some(path.clone()));
+        assert_eq!(history.entries(), ["echo \"a\nb\"", "echo a\\nb \\"]);
+        assert_eq!(unescape(r"a\qb\"), r"a\qb\");
+    }
+
+    #[test]
+    fn test_list_multiline_entries() {
+        let file = test_dir("history").join("listed");
+        let ctx = run("true", ShellOptions::new(false));
+        ctx.history.lock().unwrap().push("echo \"a\nb\"\n");
+        ctx.history.lock().unwrap().push("ls\n");
+
+        run_in(&ctx, &format!("history > {}", file.display()));
+        assert_eq!(
+            std::fs::read_to_string(&file).unwrap(),
+            "    1  echo \"a\n       b\"\n    2  ls\n"
+        );
+    }
+
+    #[test]
+    fn test_history_end_of_options() {
+        let file = test_dir("history").join("options");
+        let ctx = run("true", ShellOptions::new(false));
+        ctx.history.lock().unwrap().push("a\n");
+        ctx.history.lock().unwrap().push("b\n");
+
+        assert_eq!(run_in(&ctx, &format!("history -- 1 > {}", file.display())), 0);
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "    2  b\n");
+        assert_eq!(run_in(&ctx, "history -1"), 2);
+    }
+
+    #[test]
+    fn test_default_path() {
+        let mut env = Env::default();
+        assert_eq!(History::default_path(&env), None);
+
+        env.set("HOME", "/home/rush");
+        assert_eq!(
+            History::default_path(&env),
+            Some(PathBuf::from("/home/rush/.rush_history"))
+        );
+
+        env.set("RUSH_HISTFILE", "/tmp/history");
+        assert_eq!(History::default_path(&env), Some(PathBuf::from("/tmp/history")));
+    }
+}
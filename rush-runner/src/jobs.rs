@@ -0,0 +1,527 @@
+use std::io::Write;
+use std::str::FromStr;
+
+use nix::errno::Errno;
+use nix::sys::signal::{self, Signal};
+use nix::sys::wait::{WaitPidFlag, WaitStatus};
+use nix::unistd::{Pid, tcsetpgrp};
+
+use crate::builtins::split_options;
+use crate::result::{Error, Result};
+use crate::{ExecCtx, JobStatus, columns, update_job_statuses, waitpid_eintr};
+
+/// `kill [-SIGNAL | -s SIGNAL] [--] target...` signals jobs given as `%n`, or processes by pid,
+/// defaulting to SIGTERM. Continuing a job goes through [`continue_job`] so its status follows.
+pub(crate) fn kill(ctx: &ExecCtx<'_>, args: &[&str]) -> i32 {
+    // a `--` ends the signal option, so a negative pid (a process group) can follow it
+    let (signal, targets) = match args {
+        ["--", targets @ ..] => (Some(Signal::SIGTERM), targets),
+        ["-s", name, "--", targets @ ..] => (parse_signal(name), targets),
+        ["-s", name, targets @ ..] => (parse_signal(name), targets),
+        [flag, "--", targets @ ..] if flag.starts_with('-') && flag.len() > 1 => (parse_signal(&flag[1..]), targets),
+        [flag, targets @ ..] if flag.starts_with('-') && flag.len() > 1 => (parse_signal(&flag[1..]), targets),
+        targets => (Some(Signal::SIGTERM), targets),
+    };
+
+    let Some(signal) = signal else {
+        eprintln!("rush: kill: {}: invalid signal specification", args[0]);
+        return 2;
+    };
+    if targets.is_empty() {
+        eprintln!("rush: kill: usage: kill [-s sigspec | -sigspec] pid | %job ...");
+        return 2;
+    }
+
+    let mut status = 0;
+    for target in targets {
+        let result = match target.strip_prefix('%') {
+            Some(job) if signal == Signal::SIGCONT => parse_job_id(job).and_then(|job_id| continue_job(ctx, job_id)),
+            Some(job) => parse_job_id(job).and_then(|job_id| signal_job(ctx, job_id, signal)),
+            None => match target.parse() {
+                Ok(pid) => signal::kill(Pid::from_raw(pid), signal).map_err(Error::from),
+                Err(_) => Err(Error::NoSuchJob(target.to_string())),
+            },
+        };
+
+        if let Err(e) = result {
+            eprintln!("rush: kill: {e}");
+            status = 1;
+        }
+    }
+
+    status
+}
+
+/// `jobs` lists the job table, the current job is marked `+` and the previous one `-`, in as many
+/// columns as the terminal fits. Jobs that finished are reported once, then forgotten.
+pub(crate) fn jobs(ctx: &ExecCtx<'_>) -> i32 {
+    update_job_statuses(ctx.jobs.clone());
+    let width = columns::terminal_width(&ctx.env.lock().unwrap());
+    if let Err(e) = write!(std::io::stdout(), "{}", columns::format_columns(&list(ctx), width)) {
+        eprintln!("rush: jobs: write error: {e}");
+        return 1;
+    }
+    ctx.jobs
+        .lock()
+        .unwrap()
+        .retain(|_, job| !matches!(job.status, JobStatus::Done(_)));
+    0
+}
+
+fn list(ctx: &ExecCtx<'_>) -> Vec<String> {
+    let jobs = ctx.jobs.lock().unwrap();
+    let mut job_ids = jobs.keys().copied().collect::<Vec<_>>();
+    job_ids.sort_unstable();
+
+    let current = job_ids.last().copied();
+    let previous = job_ids.iter().rev().nth(1).copied();
+
+    job_ids
+        .iter()
+        .map(|job_id| {
+            let job = &jobs[job_id];
+            let marker = match Some(*job_id) {
+                id if id == current => '+',
+                id if id == previous => '-',
+                _ => ' ',
+            };
+            let status = match job.status {
+                JobStatus::Running => String::from("Running"),
+                JobStatus::Stopped => String::from("Stopped"),
+                JobStatus::Done(Some(0) | None) => String::from("Done"),
+                JobStatus::Done(Some(exit_code)) => format!("Exit {exit_code}"),
+            };
+            format!("[{job_id}]{marker} {status:<23} {}", job.command)
+        })
+        .collect()
+}
+
+/// `fg [%n]` hands the terminal to a job, the current one by default, resumes it if it was stopped
+/// and waits for it like any foreground command.
+pub(crate) fn fg(ctx: &ExecCtx<'_>, args: &[&str]) -> i32 {
+    let (options, args) = split_options(args);
+    if let Some(option) = options.first() {
+        eprintln!("rush: fg: {option}: invalid option");
+        return 2;
+    }
+
+    let job_id = match args {
+        [] => current_job(ctx),
+        [job] => parse_job_id(job.strip_prefix('%').unwrap_or(job)),
+        _ => {
+            eprintln!("rush: fg: too many arguments");
+            return 2;
+        }
+    };
+
+    match job_id.and_then(|job_id| foreground_job(ctx, job_id)) {
+        Ok(status) => status,
+        Err(e) => {
+            eprintln!("rush: fg: {e}");
+            1
+        }
+    }
+}
+
+fn foreground_job(ctx: &ExecCtx<'_>, job_id: u32) -> Result<i32> {
+    let (process_group_id, is_stopped) = match ctx.jobs.lock().unwrap().get(&job_id) {
+        Some(job) if !matches!(job.status, JobStatus::Done(_)) => {
+            println!("{}", job.command);
+            (job.process_group_id, matches!(job.status, JobStatus::Stopped))
+        }
+        _ => return Err(Error::NoSuchJob(format!("%{job_id}"))),
+    };
+
+    let terminal = ctx.controlling_terminal();
+    if let Some(terminal) = terminal {
+        tcsetpgrp(terminal, process_group_id)?;
+    }
+    if is_stopped {
+        continue_job(ctx, job_id)?;
+    }
+
+    let status = wait_for_job_leader(ctx, job_id, process_group_id, Some(WaitPidFlag::WUNTRACED));
+
+    // the shell takes the terminal back whatever happened to the job
+    if let Some(terminal) = terminal {
+        tcsetpgrp(terminal, ctx.shell_pgid)?;
+    }
+
+    let mut jobs = ctx.jobs.lock().unwrap();
+    match status? {
+        WaitStatus::Stopped(_, _) => {
+            let job = jobs.get_mut(&job_id).expect("the job was looked up above");
+            job.status = JobStatus::Stopped;
+            println!("\n[{job_id}]+ {:<23} {}", "Stopped", job.command);
+            Ok(128 + Signal::SIGTSTP as i32)
+        }
+        WaitStatus::Exited(_, exit_code) => {
+            jobs.remove(&job_id);
+            Ok(exit_code)
+        }
+        WaitStatus::Signaled(_, signal, _) => {
+            jobs.remove(&job_id);
+            Ok(128 + signal as i32)
+        }
+        _ => Ok(0),
+    }
+}
+
+/// `bg [%n]` resumes a stopped job, the current one by default, and leaves it in the background.
+pub(crate) fn bg(ctx: &ExecCtx<'_>, args: &[&str]) -> i32 {
+    let (options, args) = split_options(args);
+    if let Some(option) = options.first() {
+        eprintln!("rush: bg: {option}: invalid option");
+        return 2;
+    }
+
+    let job_id = match args {
+        [] => current_job(ctx),
+        [job] => parse_job_id(job.strip_prefix('%').unwrap_or(job)),
+        _ => {
+            eprintln!("rush: bg: too many arguments");
+            return 2;
+        }
+    };
+
+    match job_id.and_then(|job_id| background_job(ctx, job_id)) {
+        Ok(status) => status,
+        Err(e) => {
+            eprintln!("rush: bg: {e}");
+            1
+        }
+    }
+}
+
+fn background_job(ctx: &ExecCtx<'_>, job_id: u32) -> Result<i32> {
+    match ctx.jobs.lock().unwrap().get_mut(&job_id) {
+        Some(job) if matches!(job.status, JobStatus::Stopped) => {
+            job.is_foreground = false;
+            println!("[{job_id}] {} &", job.command);
+        }
+        Some(job) if matches!(job.status, JobStatus::Running) => {
+            eprintln!("rush: bg: job {job_id} already in background");
+            return Ok(0);
+        }
+        _ => return Err(Error::NoSuchJob(format!("%{job_id}"))),
+    }
+
+    continue_job(ctx, job_id)?;
+    Ok(0)
+}
+
+/// `wait [%n | pid]...` waits for the given jobs, or every job without arguments, and returns the
+/// status of the last one. A job or pid the shell doesn't know returns 127, like bash.
+pub(crate) fn wait(ctx: &ExecCtx<'_>, args: &[&str]) -> i32 {
+    let (options, args) = split_options(args);
+    if let Some(option) = options.first() {
+        eprintln!("rush: wait: {option}: invalid option");
+        return 2;
+    }
+
+    if args.is_empty() {
+        let mut job_ids = ctx.jobs.lock().unwrap().keys().copied().collect::<Vec<_>>();
+        job_ids.sort_unstable();
+        job_ids.into_iter().for_each(|job_id| _ = wait_for_job(ctx, job_id));
+        return 0;
+    }
+
+    let mut status = 0;
+    for target in args {
+        let job_id = match target.strip_prefix('%') {
+            Some(job) => parse_job_id(job)
+                .ok()
+                .filter(|job_id| ctx.jobs.lock().unwrap().contains_key(job_id)),
+            None => target.parse().ok().and_then(|pid| {
+                let pid = Pid::from_raw(pid);
+                let jobs = ctx.jobs.lock().unwrap();
+                jobs.iter()
+                    .find(|(_, job)| job.process_group_id == pid)
+                    .map(|(job_id, _)| *job_id)
+            }),
+        };
+
+        status = match job_id {
+            Some(job_id) => wait_for_job(ctx, job_id).unwrap_or(127),
+            None if target.starts_with('%') => {
+                eprintln!("rush: wait: {target}: no such job");
+                127
+            }
+            None => {
+                eprintln!("rush: wait: pid {target} is not a child of this shell");
+                127
+            }
+        };
+    }
+
+    status
+}
+
+/// Waits for a job to finish and forgets it, returning its exit status.
+fn wait_for_job(ctx: &ExecCtx<'_>, job_id: u32) -> Result<i32> {
+    let (process_group_id, status) = match ctx.jobs.lock().unwrap().get(&job_id) {
+        Some(job) => (job.process_group_id, job.status.clone()),
+        None => return Err(Error::NoSuchJob(format!("%{job_id}"))),
+    };
+
+    let exit_code = match status {
+        // reaped already, by the SIGCHLD bookkeeping, which may not have known how it ended
+        JobStatus::Done(exit_code) => exit_code.unwrap_or(127),
+        _ => match wait_for_job_leader(ctx, job_id, process_group_id, None)? {
+            WaitStatus::Exited(_, exit_code) => exit_code,
+            WaitStatus::Signaled(_, signal, _) => 128 + signal as i32,
+            _ => 0,
+        },
+    };
+
+    ctx.jobs.lock().unwrap().remove(&job_id);
+    Ok(exit_code)
+}
+
+/// Waits for the process leading a job. When it was reaped already (`ECHILD`), by the SIGCHLD
+/// bookkeeping or a wait elsewhere, the job table holds the only status left; a job nothing
+/// recorded a status for is marked done with an unknown one, and waits end with 127 as for a pid
+/// `wait` doesn't know.
+fn wait_for_job_leader(
+    ctx: &ExecCtx<'_>,
+    job_id: u32,
+    process_group_id: Pid,
+    flags: Option<WaitPidFlag>,
+) -> Result<WaitStatus> {
+    match waitpid_eintr(process_group_id, flags) {
+        Err(Errno::ECHILD) => {
+            let mut jobs = ctx.jobs.lock().unwrap();
+            let exit_code = match jobs.get(&job_id).map(|job| &job.status) {
+                Some(JobStatus::Done(exit_code)) => *exit_code,
+                _ => None,
+            };
+            if let Some(job) = jobs.get_mut(&job_id) {
+                job.status = JobStatus::Done(exit_code);
+            }
+            Ok(WaitStatus::Exited(process_group_id, exit_code.unwrap_or(127)))
+        }
+        status => Ok(status?),
+    }
+}
+
+/// The most recently started job that is still alive, which `fg` and `bg` act on by default.
+fn current_job(ctx: &ExecCtx<'_>) -> Result<u32> {
+    ctx.jobs
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|(_, job)| !matches!(job.status, JobStatus::Done(_)))
+        .map(|(job_id, _)| *job_id)
+        .max()
+        .ok_or_else(|| Error::NoSuchJob(String::from("current")))
+}
+
+/// Resumes a stopped job and marks it running in the job table, however the resume was asked for.
+pub(crate) fn continue_job(ctx: &ExecCtx<'_>, job_id: u32) -> Result<()> {
+    signal_job(ctx, job_id, Signal::SIGCONT)?;
+
+    if let Some(job) = ctx.jobs.lock().unwrap().get_mut(&job_id) {
+        job.status = JobStatus::Running;
+    }
+    Ok(())
+}
+
+/// Sends `signal` to every process of a job, which only has a process group of its own under
+/// job control.
+fn signal_job(ctx: &ExecCtx<'_>, job_id: u32, signal: Signal) -> Result<()> {
+    let process_group_id = match ctx.jobs.lock().unwrap().get(&job_id) {
+        Some(job) if !matches!(job.status, JobStatus::Done(_)) => job.process_group_id,
+        _ => return Err(Error::NoSuchJob(format!("%{job_id}"))),
+    };
+
+    if ctx.job_control() {
+        signal::killpg(process_group_id, signal)?;
+    } else {
+        signal::kill(process_group_id, signal)?;
+    }
+    Ok(())
+}
+
+fn parse_job_id(job: &str) -> Result<u32> {
+    job.parse().map_err(|_| Error::NoSuchJob(format!("%{job}")))
+}
+
+/// A signal by number, or by name with or without its `SIG` prefix.
+fn parse_signal(name: &str) -> Option<Signal> {
+    if let Ok(number) = name.parse::<i32>() {
+        return Signal::try_from(number).ok();
+    }
+
+    let name = name.to_ascii_uppercase();
+    Signal::from_str(&name)
+        .or_else(|_| Signal::from_str(&format!("SIG{name}")))
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ShellOptions;
+    use crate::tests::{run, run_in, test_dir};
+
+    #[test]
+    fn test_jobs_lists_running_job() {
+        let ctx = run("sleep 5 &", ShellOptions::new(false));
+        assert_eq!(list(&ctx), ["[1]+ Running                 sleep 5"]);
+
+        run_in(&ctx, "sleep 5 &");
+        let pids = ctx
+            .jobs
+            .lock()
+            .unwrap()
+            .values()
+            .map(|job| job.process_group_id)
+            .collect::<Vec<_>>();
+        ctx.jobs.lock().unwrap().get_mut(&1).unwrap().status = JobStatus::Done(Some(0));
+        assert_eq!(
+            list(&ctx),
+            [
+                "[1]- Done                    sleep 5",
+                "[2]+ Running                 sleep 5",
+            ]
+        );
+
+        assert_eq!(jobs(&ctx), 0);
+        assert_eq!(ctx.jobs.lock().unwrap().len(), 1);
+
+        for pid in pids {
+            signal::kill(pid, Signal::SIGKILL).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_jobs_in_columns() {
+        let file = test_dir("jobs").join("columns");
+        let ctx = run("sleep 5 &", ShellOptions::new(false));
+        run_in(&ctx, "sleep 5 &");
+        let pids = ctx
+            .jobs
+            .lock()
+            .unwrap()
+            .values()
+            .map(|job| job.process_group_id)
+            .collect::<Vec<_>>();
+
+        ctx.env.lock().unwrap().set("COLUMNS", "80");
+        run_in(&ctx, &format!("jobs > {}", file.display()));
+        assert_eq!(
+            std::fs::read_to_string(&file).unwrap(),
+            "[1]- Running                 sleep 5  [2]+ Running                 sleep 5\n"
+        );
+
+        // without `$COLUMNS` a file isn't a terminal that has a width
+        ctx.env.lock().unwrap().remove("COLUMNS");
+        run_in(&ctx, &format!("jobs > {}", file.display()));
+        assert_eq!(
+            std::fs::read_to_string(&file).unwrap(),
+            "[1]- Running                 sleep 5\n[2]+ Running                 sleep 5\n"
+        );
+
+        for pid in pids {
+            signal::kill(pid, Signal::SIGKILL).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_fg_resumes_stopped_job() {
+        let ctx = run("sleep 0.2 &", ShellOptions::new(false));
+        let pid = ctx.jobs.lock().unwrap()[&1].process_group_id;
+
+        run_in(&ctx, "kill -STOP %1");
+        let status = waitpid_eintr(pid, Some(WaitPidFlag::WUNTRACED)).unwrap();
+        assert!(matches!(status, WaitStatus::Stopped(_, Signal::SIGSTOP)));
+        ctx.jobs.lock().unwrap().get_mut(&1).unwrap().status = JobStatus::Stopped;
+
+        assert_eq!(run_in(&ctx, "fg %1"), 0);
+        assert!(ctx.jobs.lock().unwrap().is_empty());
+        assert_eq!(run_in(&ctx, "fg"), 1);
+    }
+
+    #[test]
+    fn test_bg_resumes_stopped_job() {
+        let ctx = run("sleep 5 &", ShellOptions::new(false));
+        let pid = ctx.jobs.lock().unwrap()[&1].process_group_id;
+
+        run_in(&ctx, "kill -STOP %1");
+        waitpid_eintr(pid, Some(WaitPidFlag::WUNTRACED)).unwrap();
+        let mut jobs = ctx.jobs.lock().unwrap();
+        let job = jobs.get_mut(&1).unwrap();
+        job.status = JobStatus::Stopped;
+        job.is_foreground = true;
+        drop(jobs);
+
+        assert_eq!(run_in(&ctx, "bg"), 0);
+        let job = ctx.jobs.lock().unwrap()[&1].clone();
+        assert!(matches!(job.status, JobStatus::Running));
+        assert!(!job.is_foreground);
+        assert_eq!(run_in(&ctx, "bg %1"), 0);
+        assert_eq!(run_in(&ctx, "bg %2"), 1);
+
+        signal::kill(pid, Signal::SIGKILL).unwrap();
+    }
+
+    #[test]
+    fn test_wait() {
+        let ctx = run("sh -c 'exit 3' &", ShellOptions::new(false));
+        assert_eq!(run_in(&ctx, "wait %1"), 3);
+        assert!(ctx.jobs.lock().unwrap().is_empty());
+
+        assert_eq!(run_in(&ctx, "wait %99"), 127);
+        assert_eq!(run_in(&ctx, "wait 999999999"), 127);
+
+        run_in(&ctx, "sleep 0.1 &");
+        let pid = ctx.last_background_pid.lock().unwrap().unwrap();
+        assert_eq!(run_in(&ctx, &format!("wait {pid}")), 0);
+
+        run_in(&ctx, "sleep 0.1 &");
+        assert_eq!(run_in(&ctx, "wait"), 0);
+        assert!(ctx.jobs.lock().unwrap().is_empty());
+
+        run_in(&ctx, "sh -c 'exit 4' &");
+        let pid = ctx.last_background_pid.lock().unwrap().unwrap();
+        assert_eq!(run_in(&ctx, &format!("wait -x {pid}")), 2);
+        assert_eq!(run_in(&ctx, &format!("wait -- {pid}")), 4);
+
+        run_in(&ctx, "sleep 5 &");
+        let pid = ctx.last_background_pid.lock().unwrap().unwrap();
+        assert_eq!(run_in(&ctx, &format!("kill -KILL -- {pid}")), 0);
+        assert_eq!(run_in(&ctx, &format!("wait {pid}")), 128 + Signal::SIGKILL as i32);
+    }
+
+    /// Runs the SIGCHLD bookkeeping until it has a final status for the job. Other tests running at
+    /// the same time may take the flag it needs first, so it is set again on every round.
+    fn update_until_done(ctx: &ExecCtx<'_>, job_id: u32) {
+        while !matches!(ctx.jobs.lock().unwrap()[&job_id].status, JobStatus::Done(_)) {
+            crate::JOBS_UPDATED.store(true, std::sync::atomic::Ordering::Relaxed);
+            update_job_statuses(ctx.jobs.clone());
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn test_wait_for_reaped_job() {
+        // the SIGCHLD bookkeeping reaps the job and records its status, which `wait` returns
+        let ctx = run("sh -c 'exit 3' &", ShellOptions::new(false));
+        update_until_done(&ctx, 1);
+        assert_eq!(run_in(&ctx, "wait %1"), 3);
+
+        // reaped without a status being recorded, waiting finds nothing but doesn't fail
+        run_in(&ctx, "sh -c 'exit 4' &");
+        let pid = ctx.last_background_pid.lock().unwrap().unwrap();
+        waitpid_eintr(pid, None).unwrap();
+        assert_eq!(run_in(&ctx, "wait %2"), 127);
+        assert!(ctx.jobs.lock().unwrap().is_empty());
+
+        run_in(&ctx, "sh -c 'exit 5' &");
+        let pid = ctx.last_background_pid.lock().unwrap().unwrap();
+        waitpid_eintr(pid, None).unwrap();
+        update_until_done(&ctx, 3);
+        assert!(matches!(ctx.jobs.lock().unwrap()[&3].status, JobStatus::Done(None)));
+        assert_eq!(list(&ctx), ["[3]+ Done                    sh -c 'exit 5'"]);
+    }
+}
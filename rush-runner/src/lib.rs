@@ -1,22 +1,44 @@
+mod builtins;
+mod columns;
+mod env;
+mod expand;
+mod glob;
+mod history;
+mod jobs;
+mod options;
+mod redirect;
 mod result;
 
 use std::collections::HashMap;
-use std::ffi::CString;
-use std::os::fd::FromRawFd;
+use std::ffi::{CStr, CString};
+use std::io::Write;
+use std::os::fd::{BorrowedFd, FromRawFd};
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
 use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
 use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
 
-use nix::fcntl::{FcntlArg, FdFlag};
+use nix::fcntl::OFlag;
 use nix::sys::signal::{self, SigHandler, Signal};
 use nix::sys::wait::{WaitPidFlag, WaitStatus, waitpid};
 use nix::unistd::{ForkResult, Pid, tcgetpgrp, tcsetpgrp};
-use rush_parser::{Ast, DisplayAst, SimpleCommand};
+use rush_parser::{AndOrOp, Ast, DisplayAst, Redirect, SimpleCommand};
 
+pub use crate::env::Env;
+pub use crate::history::History;
+pub use crate::options::ShellOptions;
 pub use crate::result::Error;
 use crate::result::Result;
 
 static JOBS_UPDATED: AtomicBool = AtomicBool::new(false);
 
+/// Exit status of a command killed for running longer than `command_timeout`, as timeout(1) does.
+const TIMEOUT_STATUS: i32 = 124;
+/// How long a timed out command gets to handle SIGTERM before it is sent SIGKILL.
+const TIMEOUT_KILL_GRACE: Duration = Duration::from_secs(1);
+
 extern "C" fn sigchld_handler(_: i32) {
     JOBS_UPDATED.store(true, Ordering::Relaxed);
 }
@@ -25,7 +47,7 @@ extern "C" fn sigchld_handler(_: i32) {
 pub enum JobStatus {
     Running,
     Stopped,
-    Done(i32), // exit code
+    Done(Option<i32>), // exit code, unknown when the job was reaped without recording it
 }
 
 #[derive(Debug, Clone)]
@@ -49,21 +71,104 @@ impl Job {
     }
 }
 
+#[derive(Clone)]
 pub struct ExecCtx<'ctx> {
     pub source: &'ctx str,
     pub jobs: Arc<Mutex<HashMap<u32, Job>>>,
     pub next_job_id: Arc<Mutex<u32>>,
     pub shell_pgid: Pid,
+    /// Process id of the shell, `$$`, kept by the subshells forked from it.
+    pub shell_pid: Pid,
+    /// Process id of the shell's parent, `$PPID`.
+    pub shell_parent_pid: Pid,
     pub shell_terminal: i32,
+    pub options: Arc<Mutex<ShellOptions>>,
+    pub last_background_pid: Arc<Mutex<Option<Pid>>>,
+    /// Exit status of every stage of the last foreground pipeline, like bash's `PIPESTATUS`.
+    pub pipe_status: Arc<Mutex<Vec<i32>>>,
+    /// Exit status of the last command that ran, `$?`.
+    pub last_status: Arc<Mutex<i32>>,
+    /// The shell's variables, the exported ones passed on to the programs it starts.
+    pub env: Arc<Mutex<Env>>,
+    /// Text of the simple command being run, `$RUSH_COMMAND`.
+    pub current_command: Arc<Mutex<String>>,
+    /// Commands entered at the prompt, listed by `history`.
+    pub history: Arc<Mutex<History>>,
+    /// Foreground commands running longer than this are killed.
+    pub command_timeout: Option<Duration>,
 }
 
-pub fn init_shell() -> Result<(Pid, i32), Box<dyn std::error::Error>> {
+impl ExecCtx<'_> {
+    /// A context sharing this shell's state, executing commands parsed from another source.
+    pub fn with_source<'src>(&self, source: &'src str) -> ExecCtx<'src> {
+        ExecCtx { source, ..self.clone() }
+    }
+
+    fn job_control(&self) -> bool {
+        self.options.lock().unwrap().monitor
+    }
+
+    /// The terminal foreground jobs are handed to, only under job control and when the shell
+    /// actually runs on one.
+    fn controlling_terminal(&self) -> Option<BorrowedFd<'static>> {
+        let terminal = unsafe { BorrowedFd::borrow_raw(self.shell_terminal) };
+        (self.job_control() && nix::unistd::isatty(terminal).unwrap_or(false)).then_some(terminal)
+    }
+
+    fn allocate_job_id(&self) -> u32 {
+        let mut next_id = self.next_job_id.lock().unwrap();
+        let id = *next_id;
+        *next_id += 1;
+        id
+    }
+
+    /// Resolves the special parameters `$?`, `$$`, `$PPID`, `$!` and `$RUSH_COMMAND` by name,
+    /// without the leading `$`.
+    pub fn special_parameter(&self, name: &str) -> Option<String> {
+        match name {
+            "?" => Some(self.last_status.lock().unwrap().to_string()),
+            "$" => Some(self.shell_pid.to_string()),
+            "PPID" => Some(self.shell_parent_pid.to_string()),
+            "!" => self.last_background_pid.lock().unwrap().map(|pid| pid.to_string()),
+            "RUSH_COMMAND" => Some(self.current_command.lock().unwrap().clone()),
+            _ => None,
+        }
+    }
+}
+
+/// Restores the default dispositions of the signals `init_shell` ignores or handles, so programs
+/// started by the shell don't inherit them across exec. Called right after every fork.
+pub(crate) fn reset_signals_for_child() {
+    for sig in [
+        Signal::SIGINT,
+        Signal::SIGQUIT,
+        Signal::SIGTSTP,
+        Signal::SIGTTIN,
+        Signal::SIGTTOU,
+        Signal::SIGCHLD,
+    ] {
+        let _ = unsafe { signal::signal(sig, SigHandler::SigDfl) };
+    }
+}
+
+/// Ignores the signals that stop a process using the terminal while it isn't in the foreground,
+/// which a shell with job control does when it takes the terminal back from a job. Interactive
+/// shells ignore them from the start, anything else once `monitor` is turned on.
+pub(crate) fn ignore_terminal_signals() {
+    for sig in [Signal::SIGTTIN, Signal::SIGTTOU] {
+        let _ = unsafe { signal::signal(sig, SigHandler::SigIgn) };
+    }
+}
+
+/// Returns the shell process group, its terminal, and whether the shell is interactive:
+/// `interactive` allows it, a shell running a `-c` string never is, and stdin is a terminal.
+pub fn init_shell(interactive: bool) -> Result<(Pid, i32, bool), Box<dyn std::error::Error>> {
     let shell_terminal = nix::libc::STDIN_FILENO;
     let stdin_fd = unsafe { std::os::fd::BorrowedFd::borrow_raw(shell_terminal) };
-    let shell_is_interactive = nix::unistd::isatty(stdin_fd)?;
+    let shell_is_interactive = interactive && nix::unistd::isatty(stdin_fd)?;
 
     if !shell_is_interactive {
-        return Ok((nix::unistd::getpid(), shell_terminal));
+        return Ok((nix::unistd::getpid(), shell_terminal, false));
     }
 
     // ignore interactive and job-control signals
@@ -91,7 +196,7 @@ pub fn init_shell() -> Result<(Pid, i32), Box<dyn std::error::Error>> {
     }
 
     tcsetpgrp(stdin_fd, shell_pgid)?; // set shell as the foreground process group
-    Ok((shell_pgid, shell_terminal))
+    Ok((shell_pgid, shell_terminal, true))
 }
 
 pub fn update_job_statuses(jobs: Arc<Mutex<HashMap<u32, Job>>>) {
@@ -107,138 +212,629 @@ pub fn update_job_statuses(jobs: Arc<Mutex<HashMap<u32, Job>>>) {
             continue;
         }
 
-        match waitpid(
-            Some(job.process_group_id),
+        let status = waitpid_eintr(
+            job.process_group_id,
             Some(WaitPidFlag::WNOHANG | WaitPidFlag::WUNTRACED),
-        ) {
-            Ok(WaitStatus::Exited(_, exit_code)) => {
-                job.status = JobStatus::Done(exit_code);
-                completed_jobs.push(*job_id);
-            }
-            Ok(WaitStatus::Signaled(_, signal, _)) => {
-                job.status = JobStatus::Done(128 + signal as i32);
-                completed_jobs.push(*job_id);
-            }
-            Ok(WaitStatus::Stopped(_, _)) => {
-                job.status = JobStatus::Stopped;
-            }
-            Ok(WaitStatus::Continued(_)) => {
-                job.status = JobStatus::Running;
-            }
+        );
+
+        match status {
+            Ok(WaitStatus::Exited(_, exit_code)) => job.status = JobStatus::Done(Some(exit_code)),
+            Ok(WaitStatus::Signaled(_, signal, _)) => job.status = JobStatus::Done(Some(128 + signal as i32)),
+            Ok(WaitStatus::Stopped(_, _)) => job.status = JobStatus::Stopped,
+            Ok(WaitStatus::Continued(_)) => job.status = JobStatus::Running,
+            // reaped by a wait elsewhere that didn't record a status, the job is gone all the same
+            Err(nix::errno::Errno::ECHILD) => job.status = JobStatus::Done(None),
             _ => {} // still running or error
         }
+
+        let message = match status {
+            Ok(status) => termination_message(status),
+            Err(nix::errno::Errno::ECHILD) => Some(String::from("Done")),
+            Err(_) => None,
+        };
+        if let Some(message) = message {
+            completed_jobs.push((*job_id, message));
+        }
     }
 
-    for job_id in completed_jobs {
+    for (job_id, message) in completed_jobs {
         if let Some(job) = jobs_lock.get(&job_id) {
-            match &job.status {
-                JobStatus::Done(0) => println!("[{}] Done                    {}", job_id, job.command),
-                JobStatus::Done(code) => println!("[{}] Exit {}                {}", job_id, code, job.command),
-                _ => {}
-            }
+            println!("[{job_id}] {message:<23} {}", job.command);
+        }
+    }
+}
+
+/// Describes how a job terminated the way bash reports it, or `None` if it is still alive.
+fn termination_message(status: WaitStatus) -> Option<String> {
+    match status {
+        WaitStatus::Exited(_, 0) => Some(String::from("Done")),
+        WaitStatus::Exited(_, exit_code) => Some(format!("Exit {exit_code}")),
+        WaitStatus::Signaled(_, signal, core_dumped) => {
+            let core_dumped = if core_dumped { " (core dumped)" } else { "" };
+            Some(format!("{}{core_dumped}", signal_description(signal)))
         }
+        _ => None,
     }
 }
 
-pub fn execute(ctx: &mut ExecCtx<'_>, commands: Ast) -> Result<()> {
-    match commands {
-        Ast::Command(cmd) => execute_command(ctx, cmd)?,
-        Ast::Pipeline(cmds) => execute_pipeline(ctx, cmds)?,
-        Ast::BackgroundJob(ast) => execute_background_job(ctx, *ast)?,
+fn signal_description(signal: Signal) -> &'static str {
+    match signal {
+        Signal::SIGHUP => "Hangup",
+        Signal::SIGINT => "Interrupt",
+        Signal::SIGQUIT => "Quit",
+        Signal::SIGILL => "Illegal instruction",
+        Signal::SIGTRAP => "Trace/breakpoint trap",
+        Signal::SIGABRT => "Aborted",
+        Signal::SIGBUS => "Bus error",
+        Signal::SIGFPE => "Floating point exception",
+        Signal::SIGKILL => "Killed",
+        Signal::SIGUSR1 => "User defined signal 1",
+        Signal::SIGSEGV => "Segmentation fault",
+        Signal::SIGUSR2 => "User defined signal 2",
+        Signal::SIGPIPE => "Broken pipe",
+        Signal::SIGALRM => "Alarm clock",
+        Signal::SIGTERM => "Terminated",
+        Signal::SIGXCPU => "CPU time limit exceeded",
+        Signal::SIGXFSZ => "File size limit exceeded",
+        Signal::SIGSYS => "Bad system call",
+        signal => signal.as_str(),
+    }
+}
+
+/// Executes the commands and returns the exit status of the last one that ran.
+pub fn execute(ctx: &mut ExecCtx<'_>, commands: Ast) -> Result<i32> {
+    let status = match commands {
+        Ast::Command(cmd) => execute_command(ctx, cmd, true),
+        Ast::Pipeline(cmds) => execute_pipeline(ctx, cmds),
+        Ast::BackgroundJob(ast) => execute_background_job(ctx, *ast),
         Ast::Sequence(seq) => {
+            let mut status = 0;
             for cmd in seq {
-                execute(ctx, cmd)?
+                status = execute(ctx, cmd)?;
             }
+            Ok(status)
         }
-    }
+        Ast::AndOr { left, op, right } => {
+            let status = execute(ctx, *left)?;
+            // the right side only runs when the left one didn't already decide the list
+            match (op, status) {
+                (AndOrOp::And, 0) => execute(ctx, *right),
+                (AndOrOp::And, _) => Ok(status),
+                (AndOrOp::Or, 0) => Ok(status),
+                (AndOrOp::Or, _) => execute(ctx, *right),
+            }
+        }
+        Ast::Not(ast) => Ok(if execute(ctx, *ast)? == 0 { 1 } else { 0 }),
+        Ast::Subshell(ast) => execute_subshell(ctx, *ast),
+    }?;
 
-    Ok(())
+    *ctx.last_status.lock().unwrap() = status;
+    Ok(status)
 }
 
-fn execute_background_job(ctx: &mut ExecCtx<'_>, ast: Ast) -> Result<()> {
-    let job_id = {
-        let mut next_id = ctx.next_job_id.lock().unwrap();
-        let id = *next_id;
-        *next_id += 1;
-        id
-    };
+/// Runs `( ... )` in a forked copy of the shell, so whatever its commands change, the working
+/// directory or variables, stays in the copy. Its status is the status of its last command.
+fn execute_subshell(ctx: &mut ExecCtx<'_>, ast: Ast) -> Result<i32> {
+    let command = ast.to_string(ctx.source);
+
+    match unsafe { nix::unistd::fork() }? {
+        ForkResult::Parent { child } => {
+            if ctx.job_control() {
+                let _ = nix::unistd::setpgid(child, child);
+            }
+            let statuses = wait_for_foreground(ctx, child, &[child], format!("({command})"))?;
+            let status = statuses[0];
+            *ctx.pipe_status.lock().unwrap() = statuses;
+            Ok(status)
+        }
+        ForkResult::Child => {
+            reset_signals_for_child();
+            if ctx.job_control() {
+                let _ = nix::unistd::setpgid(Pid::from_raw(0), Pid::from_raw(0));
+            }
+            // the whole subshell is one job, its commands stay in its process group
+            ctx.options.lock().unwrap().monitor = false;
+            let status = execute(ctx, ast).unwrap_or_else(|e| {
+                eprintln!("rush: {e}");
+                1
+            });
+            _ = std::io::stdout().flush();
+            std::process::exit(status);
+        }
+    }
+}
+
+fn execute_background_job(ctx: &mut ExecCtx<'_>, ast: Ast) -> Result<i32> {
+    let job_id = ctx.allocate_job_id();
 
     match unsafe { nix::unistd::fork() }? {
         ForkResult::Parent { child, .. } => {
+            // set from both sides so the group exists whichever process runs first
+            if ctx.job_control() {
+                let _ = nix::unistd::setpgid(child, child);
+            }
             let job = Job::new(job_id, child, ast.to_string(ctx.source), false);
             ctx.jobs.lock().unwrap().insert(job_id, job);
+            *ctx.last_background_pid.lock().unwrap() = Some(child);
             println!("[{job_id}] {child}");
         }
         ForkResult::Child => {
-            let child_pid = nix::unistd::getpid();
-            let _ = nix::unistd::setpgid(child_pid, child_pid);
-            _ = execute(ctx, ast); // NOTE: maybe if execute fails we need to do something... not sure
-            std::process::exit(0);
+            reset_signals_for_child();
+            if ctx.job_control() {
+                let child_pid = nix::unistd::getpid();
+                let _ = nix::unistd::setpgid(child_pid, child_pid);
+            }
+            // the forked child already is the subshell, so `(a; b) &` runs its body directly
+            // instead of forking once more
+            let status = match ast {
+                Ast::Subshell(ast) => execute(ctx, *ast),
+                // nor does a lone command need a fork of its own, so the job's pid is the program's
+                Ast::Command(cmd) => execute_command(ctx, cmd, false),
+                ast => execute(ctx, ast),
+            }
+            .unwrap_or(1);
+            std::process::exit(status);
         }
     }
 
-    Ok(())
+    Ok(0)
 }
 
-fn execute_command(ctx: &mut ExecCtx<'_>, cmd: SimpleCommand) -> Result<()> {
-    let program_name = cmd.program.slice(ctx.source);
-    let program_name_cstr = CString::new(program_name).unwrap();
-    let program_args_cstr = std::iter::once(program_name_cstr.clone())
-        .chain(cmd.args.iter().map(|s| CString::new(s.slice(ctx.source)).unwrap()))
-        .collect::<Vec<_>>();
+/// Runs a simple command, external programs in a forked child the shell waits for. Without `fork`
+/// the caller already is a process of its own, which the program replaces instead.
+fn execute_command(ctx: &mut ExecCtx<'_>, cmd: SimpleCommand, fork: bool) -> Result<i32> {
+    // a bare assignment sets the variables in the shell itself, and has the status of the last
+    // command substitution in it
+    if cmd.is_assignment_only() {
+        let mut status = 0;
+        for (name, value) in &cmd.assignments {
+            let (value, substitution_status) = expand::expand_value(ctx, value);
+            status = substitution_status.unwrap_or(status);
+            ctx.env.lock().unwrap().set(name.slice(ctx.source), value);
+        }
+        return Ok(status);
+    }
+
+    *ctx.current_command.lock().unwrap() = cmd.to_string(ctx.source);
+
+    // a command word that expands to nothing, like `$(true)`, leaves nothing to run, though
+    // its redirections still open their files; its status is that of the last substitution
+    let (words, substitution_status) = expand_command(ctx, &cmd);
+    let Some((program_name, expanded)) = words.split_first() else {
+        return Ok(match redirect::apply_saved(&cmd.redirects, ctx.source) {
+            Ok(_) => substitution_status.unwrap_or(0),
+            Err(e) => {
+                eprintln!("rush: {e}");
+                1
+            }
+        });
+    };
+    let program_name = program_name.as_str();
+    let args = expanded.iter().map(String::as_str).collect::<Vec<_>>();
+    if ctx.options.lock().unwrap().xtrace {
+        eprintln!(
+            "+ {}",
+            std::iter::once(program_name)
+                .chain(args.iter().copied())
+                .collect::<Vec<_>>()
+                .join(" ")
+        );
+    }
 
+    if program_name == "exec" {
+        return execute_exec(&args, &cmd.redirects, ctx.source, &child_env(ctx, &cmd));
+    }
+    // builtins run in the shell process itself, since they change its state
+    if let Some(builtin) = builtins::lookup(program_name) {
+        // other builtins only have their redirections in effect while they run
+        let _saved = match redirect::apply_saved(&cmd.redirects, ctx.source) {
+            Ok(saved) => saved,
+            Err(e) => {
+                eprintln!("rush: {e}");
+                return Ok(1);
+            }
+        };
+        return builtin(ctx, expanded);
+    }
+
+    if args.is_empty() && ctx.options.lock().unwrap().autocd && is_autocd_target(ctx, program_name) {
+        return Ok(change_directory(ctx, &["--", program_name]));
+    }
+
+    let program_args_cstr = to_cstrings(std::iter::once(program_name).chain(args.iter().copied()))?;
+    let env = child_env(ctx, &cmd);
+    if !fork {
+        exec_command(ctx, &cmd, &program_args_cstr, &env);
+    }
+
+    // under job control the command gets a process group of its own, so Ctrl-C from the terminal
+    // reaches it and not the shell; set from both sides so it exists whichever runs first
     match unsafe { nix::unistd::fork() }? {
-        ForkResult::Parent { child } => _ = nix::sys::wait::waitpid(child, None),
+        ForkResult::Parent { child } => {
+            if ctx.job_control() {
+                let _ = nix::unistd::setpgid(child, child);
+            }
+            let statuses = wait_for_foreground(ctx, child, &[child], cmd.to_string(ctx.source))?;
+            let status = statuses[0];
+            *ctx.pipe_status.lock().unwrap() = statuses;
+            Ok(status)
+        }
         ForkResult::Child => {
-            let _ = nix::unistd::execvp(&program_name_cstr, &program_args_cstr);
-            eprintln!("rush: command not found: {program_name}");
-            std::process::exit(127);
+            reset_signals_for_child();
+            if ctx.job_control() {
+                let _ = nix::unistd::setpgid(Pid::from_raw(0), Pid::from_raw(0));
+            }
+            exec_command(ctx, &cmd, &program_args_cstr, &env)
+        }
+    }
+}
+
+/// The environment a command's program runs with: the exported variables, with the command's own
+/// `NAME=value` prefixes on top. Built before forking, a lock can't be taken in the child.
+fn child_env(ctx: &ExecCtx<'_>, cmd: &SimpleCommand) -> Vec<CString> {
+    let assignments = cmd
+        .assignments
+        .iter()
+        .map(|(name, value)| (name.slice(ctx.source), expand::expand_value(ctx, value).0));
+    ctx.env.lock().unwrap().to_cstrings(assignments)
+}
+
+/// Replaces the current process with a simple command's program, once its redirections are
+/// applied. Only the shell's children get here, the shell keeps its own fds.
+fn exec_command(ctx: &ExecCtx<'_>, cmd: &SimpleCommand, program_args: &[CString], env: &[CString]) -> ! {
+    if let Err(e) = redirect::apply(&cmd.redirects, ctx.source) {
+        eprintln!("rush: {e}");
+        std::process::exit(1);
+    }
+    let _ = exec_program(&program_args[0], program_args, env);
+    eprintln!("rush: command not found: {}", program_args[0].to_string_lossy());
+    std::process::exit(127);
+}
+
+/// `cd [--] [dir]` changes the shell's working directory, to `$HOME` without a `dir` and back to
+/// `$OLDPWD` for `-`, printing where it went. `PWD` and `OLDPWD` follow the change.
+fn execute_cd(env: &mut Env, args: &[&str]) -> i32 {
+    let (options, args) = builtins::split_options(args);
+    if let Some(option) = options.first() {
+        eprintln!("rush: cd: {option}: invalid option");
+        return 2;
+    }
+
+    let (target, print) = match args {
+        [] => (env.get("HOME").map(str::to_string).ok_or("HOME not set"), false),
+        ["-"] => (env.get("OLDPWD").map(str::to_string).ok_or("OLDPWD not set"), true),
+        [dir] => (Ok(dir.to_string()), false),
+        _ => (Err("too many arguments"), false),
+    };
+    let target = match target {
+        Ok(target) => target,
+        Err(e) => {
+            eprintln!("rush: cd: {e}");
+            return 1;
+        }
+    };
+
+    let previous = env.get("PWD").map(str::to_string).or_else(|| {
+        let cwd = std::env::current_dir().ok()?;
+        Some(cwd.to_string_lossy().into_owned())
+    });
+    if let Err(e) = nix::unistd::chdir(target.as_str()) {
+        eprintln!("rush: cd: {target}: {}", e.desc());
+        return 1;
+    }
+
+    let current = std::env::current_dir().map_or(target, |cwd| cwd.to_string_lossy().into_owned());
+    if print {
+        println!("{current}");
+    }
+
+    if let Some(previous) = previous {
+        env.set("OLDPWD", previous);
+        env.export("OLDPWD");
+    }
+    env.set("PWD", current);
+    env.export("PWD");
+
+    0
+}
+
+/// `cd`, which also reports where it went to the terminal when `osc7` is set, so terminals can open
+/// new tabs in the same directory.
+pub(crate) fn change_directory(ctx: &ExecCtx<'_>, args: &[&str]) -> i32 {
+    let status = execute_cd(&mut ctx.env.lock().unwrap(), args);
+    if status != 0 || !ctx.options.lock().unwrap().osc7 {
+        return status;
+    }
+
+    let pwd = ctx
+        .env
+        .lock()
+        .unwrap()
+        .get("PWD")
+        .map(str::to_string)
+        .unwrap_or_default();
+    let host = nix::unistd::gethostname().map_or_else(|_| String::new(), |host| host.to_string_lossy().into_owned());
+    _ = write!(std::io::stdout(), "{}", osc7(&host, &pwd));
+    _ = std::io::stdout().flush();
+    status
+}
+
+/// The OSC 7 sequence reporting `path` on `host` as the working directory, with the path
+/// percent-encoded as a `file://` URL.
+fn osc7(host: &str, path: &str) -> String {
+    let path = path
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => (byte as char).to_string(),
+            byte => format!("%{byte:02X}"),
+        })
+        .collect::<String>();
+    format!("\x1b]7;file://{host}{path}\x1b\\")
+}
+
+/// Whether `autocd` turns running `name` into a `cd`: it names a directory, and no program in
+/// `PATH` has the same name.
+fn is_autocd_target(ctx: &ExecCtx<'_>, name: &str) -> bool {
+    if !std::path::Path::new(name).is_dir() {
+        return false;
+    }
+
+    let path = ctx.env.lock().unwrap().get("PATH").map(std::ffi::OsString::from);
+    name.contains('/') || CString::new(name).is_ok_and(|name| find_in_path(&name, path.as_deref()).is_none())
+}
+
+/// `pwd [-LP]` prints the working directory: `$PWD` by default, which `cd` and startup keep
+/// pointing at it, or with `-P` the physical path with every symlink resolved.
+pub(crate) fn execute_pwd(env: &Env, args: &[&str]) -> i32 {
+    let (options, _) = builtins::split_options(args);
+    let physical = match options.last() {
+        None | Some(&"-L") => false,
+        Some(&"-P") => true,
+        Some(arg) => {
+            eprintln!("rush: pwd: {arg}: invalid option");
+            return 2;
+        }
+    };
+
+    let cwd = match env.get("PWD") {
+        Some(pwd) if !physical => Ok(std::path::PathBuf::from(pwd)),
+        _ => std::env::current_dir().and_then(std::fs::canonicalize),
+    };
+    match cwd {
+        Ok(cwd) => match writeln!(std::io::stdout(), "{}", cwd.display()) {
+            Ok(()) => 0,
+            Err(e) => {
+                eprintln!("rush: pwd: write error: {e}");
+                1
+            }
+        },
+        Err(e) => {
+            eprintln!("rush: pwd: {e}");
+            1
         }
     }
+}
+
+/// Runs `program` with the environment `env`, searching the `PATH` in it like `execvp` would. Like
+/// bash, a file the kernel refuses to execute (a script without a shebang, most often) is run with
+/// `/bin/sh` instead. Only returns when neither could run it.
+fn exec_program(program: &CStr, args: &[CString], env: &[CString]) -> nix::Error {
+    let path = env
+        .iter()
+        .find_map(|var| var.to_bytes().strip_prefix(b"PATH="))
+        .map(std::ffi::OsStr::from_bytes);
+    let Some(program) = find_in_path(program, path) else {
+        return nix::errno::Errno::ENOENT;
+    };
 
-    Ok(())
+    let Err(e) = nix::unistd::execve(&program, args, env);
+    if e != nix::errno::Errno::ENOEXEC {
+        return e;
+    }
+
+    let sh_args = [c"/bin/sh".to_owned(), program]
+        .into_iter()
+        .chain(args[1..].iter().cloned())
+        .collect::<Vec<_>>();
+    let Err(e) = nix::unistd::execve(c"/bin/sh", &sh_args, env);
+    e
+}
+
+/// The path `program` resolves to: itself when it contains a slash, otherwise the first executable
+/// file named after it in `path`, directories don't count.
+fn find_in_path(program: &CStr, path: Option<&std::ffi::OsStr>) -> Option<CString> {
+    if program.to_bytes().contains(&b'/') {
+        return Some(program.to_owned());
+    }
+
+    let name = std::ffi::OsStr::from_bytes(program.to_bytes());
+    std::env::split_paths(path?)
+        .map(|dir| dir.join(name))
+        .find(|path| path.is_file() && nix::unistd::access(path, nix::unistd::AccessFlags::X_OK).is_ok())
+        .and_then(|path| CString::new(path.into_os_string().into_vec()).ok())
 }
 
-fn execute_pipeline(ctx: &mut ExecCtx<'_>, commands: Vec<SimpleCommand>) -> Result<()> {
+/// `exec [-a name] command [args...]` replaces the shell with `command`, running it with `name`
+/// as its `argv[0]` when given. Only returns when the command could not be executed.
+///
+/// Its redirections are applied to the shell itself, so without a command they stay in effect for
+/// the rest of the session, as in `exec 3>&1 >log`.
+fn execute_exec(args: &[&str], redirects: &[Redirect], source: &str, env: &[CString]) -> Result<i32> {
+    if let Err(e) = redirect::apply(redirects, source) {
+        eprintln!("rush: exec: {e}");
+        return Ok(1);
+    }
+
+    let (argv0, args) = match args {
+        ["-a", name, args @ ..] => (Some(*name), args),
+        args => (None, args),
+    };
+    let (options, args) = builtins::split_options(args);
+    if let Some(option) = options.first() {
+        eprintln!("rush: exec: {option}: invalid option");
+        return Ok(2);
+    }
+
+    let Some((program, program_args)) = args.split_first() else { return Ok(0) };
+    let program_cstr = to_cstrings([*program])?.remove(0);
+    let program_args_cstr = to_cstrings(std::iter::once(argv0.unwrap_or(program)).chain(program_args.iter().copied()))?;
+
+    let _ = exec_program(&program_cstr, &program_args_cstr, env);
+    eprintln!("rush: exec: {program}: not found");
+    Ok(127)
+}
+
+/// The expanded program name and arguments of a command, the command word going through the same
+/// expansions as its arguments, and the exit status of the last command substitution in them.
+/// Empty when every word expands to nothing, otherwise the first field is the program. Unless
+/// `noglob` is set, each unquoted field that is a glob pattern becomes the paths it matches.
+fn expand_command(ctx: &ExecCtx<'_>, cmd: &SimpleCommand) -> (Vec<String>, Option<i32>) {
+    let noglob = ctx.options.lock().unwrap().noglob;
+    let mut status = None;
+
+    let words = std::iter::once(&cmd.program)
+        .chain(&cmd.args)
+        .flat_map(|arg| {
+            let (fields, substitution_status) = expand::expand_word(ctx, arg);
+            status = substitution_status.or(status);
+            // a word with quoted parts is left alone like a quoted one, glob doesn't know which of
+            // its characters were quoted
+            if arg.quoted || noglob || arg.value(ctx.source).contains(['\'', '"']) {
+                return fields;
+            }
+            fields
+                .into_iter()
+                .flat_map(|field| glob::glob_expand(&field).unwrap_or_else(|| vec![field]))
+                .collect()
+        })
+        .collect::<Vec<_>>();
+    (words, status)
+}
+
+/// Converts arguments for exec, which can't pass an argument holding a NUL byte.
+fn to_cstrings<'a>(args: impl IntoIterator<Item = &'a str>) -> Result<Vec<CString>> {
+    args.into_iter()
+        .map(|arg| CString::new(arg).map_err(|_| Error::InvalidArgument(arg.to_string())))
+        .collect()
+}
+
+/// `echo [-neE] args...` prints its arguments separated by spaces. `-n` drops the trailing newline
+/// and `-e` interprets the escapes `\n`, `\t` and `\\`, `-E` turns them off again.
+pub(crate) fn execute_echo(args: &[&str]) -> i32 {
+    let mut newline = true;
+    let mut escapes = false;
+
+    // flags only count before the first argument, and only when every letter is one
+    let flags = args
+        .iter()
+        .take_while(|arg| arg.len() > 1 && arg.starts_with('-') && arg[1..].chars().all(|c| "neE".contains(c)))
+        .count();
+    for flag in args[..flags].iter().flat_map(|arg| arg[1..].chars()) {
+        match flag {
+            'n' => newline = false,
+            'e' => escapes = true,
+            _ => escapes = false,
+        }
+    }
+
+    let mut output = args[flags..].join(" ");
+    if escapes {
+        output = unescape(&output);
+    }
+    if newline {
+        output.push('\n');
+    }
+
+    let mut stdout = std::io::stdout().lock();
+    match stdout.write_all(output.as_bytes()).and_then(|_| stdout.flush()) {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("rush: echo: write error: {e}");
+            1
+        }
+    }
+}
+
+/// Interprets `echo -e` escapes, leaving a backslash before anything else as it is.
+fn unescape(text: &str) -> String {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut chars = text.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            Some('n') => unescaped.push('\n'),
+            Some('t') => unescaped.push('\t'),
+            Some('\\') => unescaped.push('\\'),
+            Some(other) => unescaped.extend(['\\', other]),
+            None => unescaped.push('\\'),
+        }
+    }
+
+    unescaped
+}
+
+/// `eval args...` joins its arguments with spaces and runs the result in the current shell.
+pub(crate) fn execute_eval(ctx: &ExecCtx<'_>, source: &str) -> Result<i32> {
+    let tokens = rush_lexer::Lexer::new(source).lex();
+    if tokens.peek() == rush_lexer::TokenKind::Eof {
+        return Ok(0);
+    }
+
+    match rush_parser::Parser::new().parse(tokens) {
+        Ok(ast) => execute(&mut ctx.with_source(source), ast),
+        Err(e) => {
+            eprintln!("rush: eval: {e}");
+            Ok(2)
+        }
+    }
+}
+
+fn execute_pipeline(ctx: &mut ExecCtx<'_>, commands: Vec<SimpleCommand>) -> Result<i32> {
     if commands.is_empty() {
-        return Ok(());
+        return Ok(0);
     }
 
+    let job_command = Ast::Pipeline(commands.clone()).to_string(ctx.source);
     let mut programs = vec![];
     for command in commands.iter() {
-        let program_name = command.program.slice(ctx.source);
-        let program_args = command.args.iter().map(|arg| arg.slice(ctx.source)).collect::<Vec<_>>();
-
-        let mut program_command = vec![];
+        *ctx.current_command.lock().unwrap() = command.to_string(ctx.source);
+        let (mut program_args, _) = expand_command(ctx, command);
+        let program_name = match program_args.is_empty() {
+            true => String::new(),
+            false => program_args.remove(0),
+        };
+        let program_command =
+            to_cstrings(std::iter::once(program_name.as_str()).chain(program_args.iter().map(String::as_str)))?;
 
-        program_command.push(CString::new(program_name).unwrap());
-        program_args
-            .into_iter()
-            .for_each(|arg| program_command.push(CString::new(arg).unwrap()));
-
-        programs.push(program_command);
+        programs.push((program_name, program_args, program_command, child_env(ctx, command)));
     }
 
     let mut pipes = vec![];
     for _ in 0..(commands.len() - 1) {
-        let (read, write) = nix::unistd::pipe().unwrap();
-        nix::fcntl::fcntl(&read, FcntlArg::F_SETFD(FdFlag::FD_CLOEXEC)).ok();
-        nix::fcntl::fcntl(&write, FcntlArg::F_SETFD(FdFlag::FD_CLOEXEC)).ok();
-        pipes.push((read, write));
+        pipes.push(nix::unistd::pipe2(OFlag::O_CLOEXEC)?);
     }
 
     let mut child_pids = vec![];
     let mut process_group_id: Option<nix::unistd::Pid> = None;
+    let job_control = ctx.job_control();
 
     for idx in 0..commands.len() {
         match unsafe { nix::unistd::fork() }? {
             ForkResult::Child => {
-                let child_pid = nix::unistd::getpid();
-                let target_process_group_id = process_group_id.unwrap_or(child_pid);
+                reset_signals_for_child();
                 let mut stdin_fd = unsafe { std::os::fd::OwnedFd::from_raw_fd(nix::libc::STDIN_FILENO) };
                 let mut stdout_fd = unsafe { std::os::fd::OwnedFd::from_raw_fd(nix::libc::STDOUT_FILENO) };
 
-                let _ = nix::unistd::setpgid(nix::unistd::Pid::from_raw(0), target_process_group_id);
+                if job_control {
+                    let child_pid = nix::unistd::getpid();
+                    let target_process_group_id = process_group_id.unwrap_or(child_pid);
+                    let _ = nix::unistd::setpgid(nix::unistd::Pid::from_raw(0), target_process_group_id);
+                }
 
                 // if this is not the first process then we need to wire its stdin
                 if idx > 0 {
@@ -252,16 +848,31 @@ fn execute_pipeline(ctx: &mut ExecCtx<'_>, commands: Vec<SimpleCommand>) -> Resu
                     _ = nix::unistd::dup2(write, &mut stdout_fd);
                 }
 
-                // close all pipe fds in child (they were dup'd)
-                for (rfd, wfd) in &pipes {
-                    let _ = nix::unistd::close(rfd.try_clone().unwrap());
-                    let _ = nix::unistd::close(wfd.try_clone().unwrap());
+                // the stage only keeps its stdin and stdout, any other pipe end left open would keep
+                // a reader waiting for a writer that never comes
+                drop(std::mem::take(&mut pipes));
+
+                // explicit redirections win over the pipe wiring above
+                if let Err(e) = redirect::apply(&commands[idx].redirects, ctx.source) {
+                    eprintln!("rush: {e}");
+                    std::process::exit(1);
                 }
 
-                let program_name = &programs[idx][0];
-                let program_args = &programs[idx];
+                let (program_name, args, program_args, env) = &programs[idx];
+
+                // a builtin stage runs in its own copy of the shell, so `echo hi | cat` needs no
+                // program, but `cd dir | cat` changes nothing for the shell, like in bash
+                if let Some(builtin) = builtins::lookup(program_name) {
+                    ctx.options.lock().unwrap().monitor = false;
+                    let status = builtin(ctx, args).unwrap_or_else(|e| {
+                        eprintln!("rush: {e}");
+                        1
+                    });
+                    _ = std::io::stdout().flush();
+                    std::process::exit(status);
+                }
 
-                let _ = nix::unistd::execvp(program_name, program_args);
+                let _ = exec_program(&program_args[0], program_args, env);
                 std::process::exit(127);
             }
             ForkResult::Parent { child } => {
@@ -269,31 +880,1018 @@ fn execute_pipeline(ctx: &mut ExecCtx<'_>, commands: Vec<SimpleCommand>) -> Resu
                     process_group_id = Some(child);
                 }
 
-                let target_process_group_id = process_group_id.unwrap();
-                let _ = nix::unistd::setpgid(child, target_process_group_id);
+                if job_control {
+                    let target_process_group_id = process_group_id.unwrap();
+                    let _ = nix::unistd::setpgid(child, target_process_group_id);
+                }
                 child_pids.push(child);
             }
         }
     }
 
-    // close all remaining pipe file descriptors in parent
-    for (rfd, wfd) in pipes {
-        let _ = nix::unistd::close(rfd);
-        let _ = nix::unistd::close(wfd);
+    // the shell itself neither reads nor writes any pipe
+    drop(pipes);
+
+    let process_group_id = process_group_id.expect("a pipeline has at least one stage");
+    let statuses = wait_for_foreground(ctx, process_group_id, &child_pids, job_command)?;
+
+    let status = pipeline_status(&statuses, ctx.options.lock().unwrap().pipefail);
+    *ctx.pipe_status.lock().unwrap() = statuses;
+    Ok(status)
+}
+
+/// Waits for every process of a foreground job, which holds the terminal meanwhile under job
+/// control. A job that gets stopped (Ctrl-Z) is moved into the job table instead, with its stopped
+/// processes reporting `128 + SIGTSTP` like bash.
+fn wait_for_foreground(ctx: &ExecCtx<'_>, process_group_id: Pid, pids: &[Pid], command: String) -> Result<Vec<i32>> {
+    let terminal = ctx.controlling_terminal();
+    if let Some(terminal) = terminal {
+        tcsetpgrp(terminal, process_group_id)?;
+    }
+
+    // a hanging job is killed as a whole, by its process group when it has one of its own so the
+    // processes it started go with it
+    let timeout = ctx
+        .command_timeout
+        .map(|timeout| JobTimeout::start(timeout, ctx.job_control().then_some(process_group_id), pids));
+    let statuses = pids
+        .iter()
+        .map(|pid| {
+            let status = wait_for_child(*pid).unwrap_or(Some(1));
+            // processes the timeout killed report it, the ones that were done by then their status
+            let killed = [Signal::SIGTERM, Signal::SIGKILL].map(|signal| Some(128 + signal as i32));
+            match &timeout {
+                Some(timeout) if timeout.reaped(*pid) && killed.contains(&status) => Some(TIMEOUT_STATUS),
+                _ => status,
+            }
+        })
+        .collect::<Vec<_>>();
+    if let Some(timeout) = timeout {
+        timeout.cancel();
+    }
+
+    // the shell takes the terminal back whatever happened to the job
+    if let Some(terminal) = terminal {
+        tcsetpgrp(terminal, ctx.shell_pgid)?;
+    }
+
+    if statuses.contains(&None) {
+        let job_id = ctx.allocate_job_id();
+        println!("\n[{job_id}]+ {:<23} {command}", "Stopped");
+
+        let mut job = Job::new(job_id, process_group_id, command, true);
+        job.status = JobStatus::Stopped;
+        ctx.jobs.lock().unwrap().insert(job_id, job);
+    }
+
+    let stopped_status = 128 + Signal::SIGTSTP as i32;
+    Ok(statuses
+        .into_iter()
+        .map(|status| status.unwrap_or(stopped_status))
+        .collect())
+}
+
+/// A pipeline reports the status of its last stage, or with `pipefail` the status of the
+/// rightmost stage that failed.
+fn pipeline_status(statuses: &[i32], pipefail: bool) -> i32 {
+    if pipefail {
+        return statuses.iter().rev().find(|&&status| status != 0).copied().unwrap_or(0);
+    }
+
+    statuses.last().copied().unwrap_or(0)
+}
+
+/// Waits for a foreground child to terminate and returns its exit status, or `None` if it was
+/// stopped instead.
+fn wait_for_child(child: Pid) -> Result<Option<i32>> {
+    loop {
+        match waitpid_eintr(child, Some(WaitPidFlag::WUNTRACED))? {
+            WaitStatus::Exited(_, exit_code) => return Ok(Some(exit_code)),
+            WaitStatus::Signaled(_, signal, _) => return Ok(Some(128 + signal as i32)),
+            WaitStatus::Stopped(_, _) => return Ok(None),
+            _ => continue,
+        }
     }
+}
+
+/// Kills a foreground job running longer than `command_timeout`: SIGTERM first, then SIGKILL if
+/// it is still alive after a grace period. The timer runs on a thread of its own, so the shell
+/// blocks waiting for the job meanwhile.
+struct JobTimeout {
+    cancel: mpsc::Sender<()>,
+    expired: Arc<AtomicBool>,
+    /// The job's processes not reaped yet, the ones signalled when it has no process group.
+    alive: Arc<Mutex<Vec<Pid>>>,
+    thread: JoinHandle<()>,
+}
 
-    for pid in &child_pids {
-        loop {
-            match nix::sys::wait::waitpid(Some(*pid), None) {
-                Ok(WaitStatus::Exited(_, _)) => break,
-                Ok(WaitStatus::Signaled(_, _, _)) => break,
-                Ok(WaitStatus::Stopped(_, _)) => continue,
-                Ok(WaitStatus::Continued(_)) => continue,
-                Ok(WaitStatus::StillAlive) => continue,
-                Err(_) => break,
+impl JobTimeout {
+    fn start(timeout: Duration, process_group_id: Option<Pid>, pids: &[Pid]) -> Self {
+        let (cancel, cancelled) = mpsc::channel();
+        let expired = Arc::new(AtomicBool::new(false));
+        let alive = Arc::new(Mutex::new(pids.to_vec()));
+
+        let thread = std::thread::spawn({
+            let (expired, alive) = (expired.clone(), alive.clone());
+            move || {
+                // a process that exited in the meantime (ESRCH) is no reason to give up on the rest
+                let kill = |signal| match process_group_id {
+                    Some(process_group_id) => _ = signal::killpg(process_group_id, signal),
+                    None => alive
+                        .lock()
+                        .unwrap()
+                        .iter()
+                        .for_each(|pid| _ = signal::kill(*pid, signal)),
+                };
+
+                if cancelled.recv_timeout(timeout) != Err(RecvTimeoutError::Timeout) {
+                    return;
+                }
+                expired.store(true, Ordering::Relaxed);
+                kill(Signal::SIGTERM);
+
+                if cancelled.recv_timeout(TIMEOUT_KILL_GRACE) == Err(RecvTimeoutError::Timeout) {
+                    kill(Signal::SIGKILL);
+                }
             }
+        });
+
+        Self {
+            cancel,
+            expired,
+            alive,
+            thread,
         }
     }
 
-    Ok(())
+    /// Records that `pid` was reaped, returning whether the job had timed out by then.
+    fn reaped(&self, pid: Pid) -> bool {
+        self.alive.lock().unwrap().retain(|alive| *alive != pid);
+        self.expired.load(Ordering::Relaxed)
+    }
+
+    /// Stops the timer once the job is over, whether it expired or not.
+    fn cancel(self) {
+        drop(self.cancel);
+        _ = self.thread.join();
+    }
+}
+
+/// `waitpid` that retries when a signal handler (SIGCHLD, most often) interrupts the wait, instead
+/// of reporting `EINTR` as a failure to wait for the child.
+pub(crate) fn waitpid_eintr(pid: Pid, flags: Option<WaitPidFlag>) -> nix::Result<WaitStatus> {
+    loop {
+        match waitpid(pid, flags) {
+            Err(nix::errno::Errno::EINTR) => continue,
+            result => return result,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use std::time::Instant;
+
+    use super::*;
+
+    pub(crate) fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("rush-runner-{}-{name}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    pub(crate) fn run(source: &str, options: ShellOptions) -> ExecCtx<'_> {
+        run_with_status(source, options).0
+    }
+
+    fn run_with_status(source: &str, options: ShellOptions) -> (ExecCtx<'_>, i32) {
+        let (ctx, status) = try_run(source, options);
+        (ctx, status.unwrap())
+    }
+
+    /// Runs `source` with the state of a shell that already ran something.
+    pub(crate) fn run_in(ctx: &ExecCtx<'_>, source: &str) -> i32 {
+        let ast = rush_parser::Parser::new()
+            .parse(rush_lexer::Lexer::new(source).lex())
+            .unwrap();
+        execute(&mut ctx.with_source(source), ast).unwrap()
+    }
+
+    fn try_run(source: &str, options: ShellOptions) -> (ExecCtx<'_>, Result<i32>) {
+        let tokens = rush_lexer::Lexer::new(source).lex();
+        let ast = rush_parser::Parser::new().parse(tokens).unwrap();
+        let mut ctx = ExecCtx {
+            source,
+            jobs: Arc::new(Mutex::new(HashMap::new())),
+            next_job_id: Arc::new(Mutex::new(1)),
+            shell_pgid: nix::unistd::getpgrp(),
+            shell_pid: nix::unistd::getpid(),
+            shell_parent_pid: nix::unistd::getppid(),
+            shell_terminal: nix::libc::STDIN_FILENO,
+            options: Arc::new(Mutex::new(options)),
+            last_background_pid: Arc::new(Mutex::new(None)),
+            pipe_status: Arc::new(Mutex::new(vec![])),
+            last_status: Arc::new(Mutex::new(0)),
+            env: Arc::new(Mutex::new(Env::from_process())),
+            current_command: Arc::new(Mutex::new(String::new())),
+            history: Arc::new(Mutex::new(History::default())),
+            command_timeout: None,
+        };
+
+        let status = execute(&mut ctx, ast);
+        (ctx, status)
+    }
+
+    #[test]
+    fn test_pipeline_process_group_follows_monitor() {
+        let dir = test_dir("pipeline_process_group");
+        let script = dir.join("pgid.sh");
+        let output = dir.join("pgid");
+        std::fs::write(&script, format!("ps -o pgid= -p $$ > {}\n", output.display())).unwrap();
+        let source = format!("sh {} | cat", script.display());
+        let pgid = || std::fs::read_to_string(&output).unwrap().trim().parse::<i32>().unwrap();
+
+        run(&source, ShellOptions::new(false));
+        assert_eq!(pgid(), nix::unistd::getpgrp().as_raw());
+
+        run(&source, ShellOptions::new(true));
+        assert_ne!(pgid(), nix::unistd::getpgrp().as_raw());
+    }
+
+    #[test]
+    fn test_special_parameters() {
+        let ctx = run("sleep 0 &", ShellOptions::new(false));
+        let job_pid = ctx.jobs.lock().unwrap()[&1].process_group_id;
+
+        assert_eq!(ctx.special_parameter("!"), Some(job_pid.to_string()));
+        assert_eq!(ctx.special_parameter("$"), Some(std::process::id().to_string()));
+        assert_eq!(ctx.special_parameter("PPID"), Some(nix::unistd::getppid().to_string()));
+    }
+
+    #[test]
+    fn test_subshells_keep_the_shell_pid() {
+        let ctx = run("PID=$(echo $$); PARENT=$(echo $PPID)", ShellOptions::new(false));
+        let env = ctx.env.lock().unwrap();
+
+        assert_eq!(env.get("PID"), ctx.special_parameter("$").as_deref());
+        assert_eq!(env.get("PARENT"), ctx.special_parameter("PPID").as_deref());
+    }
+
+    #[test]
+    fn test_command_timeout_kills_child() {
+        let mut ctx = run("true", ShellOptions::new(false));
+        ctx.command_timeout = Some(Duration::from_millis(100));
+        let started = Instant::now();
+
+        assert_eq!(run_in(&ctx, "sleep 5"), TIMEOUT_STATUS);
+        assert!(started.elapsed() < Duration::from_secs(5));
+
+        // a command done in time keeps its status, even in a pipeline with one that isn't
+        assert_eq!(run_in(&ctx, "sh -c 'exit 3'"), 3);
+        assert_eq!(run_in(&ctx, "sleep 5 | true"), 0);
+        assert_eq!(*ctx.pipe_status.lock().unwrap(), [TIMEOUT_STATUS, 0]);
+    }
+
+    #[test]
+    fn test_command_timeout_kills_process_group() {
+        let file = test_dir("timeout_group").join("survived");
+        _ = std::fs::remove_file(&file);
+        let mut ctx = run("true", ShellOptions::new(true));
+        ctx.command_timeout = Some(Duration::from_millis(100));
+
+        // the processes the command started in the background are killed along with it
+        let source = format!("sh -c '(sleep 0.5; echo survived > {}) & wait'", file.display());
+        assert_eq!(run_in(&ctx, &source), TIMEOUT_STATUS);
+        std::thread::sleep(Duration::from_secs(1));
+        assert!(!file.exists());
+    }
+
+    #[test]
+    fn test_waitpid_retries_when_interrupted() {
+        static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+        extern "C" fn handler(_: nix::libc::c_int) {
+            INTERRUPTED.store(true, Ordering::Relaxed);
+        }
+
+        // without SA_RESTART the signal makes the blocking waitpid fail with EINTR
+        let action = signal::SigAction::new(
+            SigHandler::Handler(handler),
+            signal::SaFlags::empty(),
+            signal::SigSet::empty(),
+        );
+        unsafe { signal::sigaction(Signal::SIGUSR1, &action) }.unwrap();
+
+        let ForkResult::Parent { child } = (unsafe { nix::unistd::fork() }).unwrap() else {
+            let sleep = [c"sleep", c"0.3"];
+            let _ = nix::unistd::execvp(sleep[0], &sleep);
+            std::process::exit(127);
+        };
+
+        let waiting_thread = unsafe { nix::libc::pthread_self() };
+        std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(50));
+            unsafe { nix::libc::pthread_kill(waiting_thread, nix::libc::SIGUSR1) };
+        });
+
+        assert_eq!(waitpid_eintr(child, None), Ok(WaitStatus::Exited(child, 0)));
+        assert!(INTERRUPTED.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn test_exec_with_custom_argv0() {
+        let dir = test_dir("exec_argv0");
+        let script = dir.join("args.sh");
+        let output = dir.join("args");
+        std::fs::write(&script, format!("ps -o args= -p $$ > {}\n", output.display())).unwrap();
+
+        match unsafe { nix::unistd::fork() }.unwrap() {
+            ForkResult::Parent { child } => _ = waitpid(child, None).unwrap(),
+            ForkResult::Child => {
+                run(
+                    &format!("exec -a custom sh {}", script.display()),
+                    ShellOptions::new(false),
+                );
+                std::process::exit(127);
+            }
+        }
+
+        let args = std::fs::read_to_string(&output).unwrap();
+        assert_eq!(args.trim(), format!("custom {}", script.display()));
+    }
+
+    #[test]
+    fn test_exec_end_of_options() {
+        // neither replaces the shell: one is an invalid option, the other a program that doesn't
+        // exist
+        assert_eq!(run_with_status("exec -x true", ShellOptions::new(false)).1, 2);
+        assert_eq!(run_with_status("exec -- -x", ShellOptions::new(false)).1, 127);
+    }
+
+    #[test]
+    fn test_exec_redirects_shell_fds_in_order() {
+        let dir = test_dir("exec_redirects");
+        let script = dir.join("write.sh");
+        let log = dir.join("log");
+        std::fs::write(&script, "echo terminal >&3\necho log\n").unwrap();
+
+        // close-on-exec, or commands forked by other tests would hold the write end open
+        let (read, write) = nix::unistd::pipe2(OFlag::O_CLOEXEC).unwrap();
+        match unsafe { nix::unistd::fork() }.unwrap() {
+            ForkResult::Parent { child } => {
+                drop(write);
+                waitpid(child, None).unwrap();
+            }
+            ForkResult::Child => {
+                // the pipe stands in for the terminal the shell's stdout was on
+                nix::unistd::dup2_stdout(&write).unwrap();
+                run(&format!("exec 3>&1 > {}", log.display()), ShellOptions::new(false));
+                run(&format!("sh {}", script.display()), ShellOptions::new(false));
+                std::process::exit(0);
+            }
+        }
+
+        let terminal = std::io::read_to_string(std::fs::File::from(read)).unwrap();
+        assert_eq!(terminal, "terminal\n");
+        assert_eq!(std::fs::read_to_string(&log).unwrap(), "log\n");
+    }
+
+    #[test]
+    fn test_termination_message() {
+        let pid = Pid::from_raw(1);
+
+        assert_eq!(termination_message(WaitStatus::Exited(pid, 0)).unwrap(), "Done");
+        assert_eq!(termination_message(WaitStatus::Exited(pid, 2)).unwrap(), "Exit 2");
+        assert_eq!(
+            termination_message(WaitStatus::Signaled(pid, Signal::SIGSEGV, true)).unwrap(),
+            "Segmentation fault (core dumped)"
+        );
+        assert_eq!(
+            termination_message(WaitStatus::Signaled(pid, Signal::SIGTERM, false)).unwrap(),
+            "Terminated"
+        );
+        assert_eq!(termination_message(WaitStatus::StillAlive), None);
+    }
+
+    #[test]
+    fn test_eval_runs_in_current_shell() {
+        let dir = test_dir("eval");
+        let source = format!("eval set -o monitor ';' touch {}/a '|' cat", dir.display());
+
+        let ctx = run(&source, ShellOptions::new(false));
+
+        assert!(ctx.options.lock().unwrap().monitor);
+        assert!(dir.join("a").exists());
+
+        let file = dir.join("out");
+        run(
+            &format!("eval 'x=5'; echo $x > {}", file.display()),
+            ShellOptions::new(false),
+        );
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "5\n");
+    }
+
+    #[test]
+    fn test_only_exported_variables_reach_programs() {
+        let file = test_dir("exported").join("out");
+        let ctx = run("SHELL_ONLY=1 && export EXPORTED=2", ShellOptions::new(false));
+        let printenv = format!("printenv SHELL_ONLY EXPORTED PREFIX > {}", file.display());
+
+        run_in(&ctx, &format!("PREFIX=3 {printenv}"));
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "2\n3\n");
+
+        run_in(&ctx, &format!("export SHELL_ONLY; {printenv}"));
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "1\n2\n");
+    }
+
+    #[test]
+    fn test_expanded_program_name() {
+        let dir = test_dir("program-name");
+        let file = dir.join("out");
+        let ctx = run("true", ShellOptions::new(false));
+        ctx.env.lock().unwrap().set("E", "echo");
+        ctx.env.lock().unwrap().set("CAT", "cat");
+
+        assert_eq!(run_in(&ctx, &format!("$E hi > {}", file.display())), 0);
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "hi\n");
+
+        assert_eq!(run_in(&ctx, &format!("$E piped | $CAT > {}", file.display())), 0);
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "piped\n");
+
+        // an empty command word leaves its first argument to be the program
+        assert_eq!(run_in(&ctx, &format!("$(true) echo shifted > {}", file.display())), 0);
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "shifted\n");
+        assert_eq!(run_in(&ctx, "$(true)"), 0);
+    }
+
+    #[test]
+    fn test_command_substitution_status() {
+        let ctx = run("X=$(false)", ShellOptions::new(false));
+        assert_eq!(ctx.special_parameter("?"), Some(String::from("1")));
+
+        assert_eq!(run_in(&ctx, "$(false)"), 1);
+        assert_eq!(run_in(&ctx, "X=$(sh -c 'exit 3') Y=$(true)"), 0);
+        assert_eq!(run_in(&ctx, "X=$(true) Y=$(sh -c 'exit 3')"), 3);
+        // with no substitution a bare assignment succeeds, whatever ran before
+        assert_eq!(run_in(&ctx, "false; X=$?"), 0);
+        assert_eq!(ctx.env.lock().unwrap().get("X"), Some("1"));
+        // a command that runs reports its own status
+        assert_eq!(run_in(&ctx, "$(false) true"), 0);
+    }
+
+    #[test]
+    fn test_pipe_status() {
+        let ctx = run("false | true | false", ShellOptions::new(false));
+        assert_eq!(*ctx.pipe_status.lock().unwrap(), [1, 0, 1]);
+
+        let ctx = run("true; false", ShellOptions::new(false));
+        assert_eq!(*ctx.pipe_status.lock().unwrap(), [1]);
+    }
+
+    #[test]
+    fn test_pipefail_status() {
+        let mut options = ShellOptions::new(false);
+        assert_eq!(run_with_status("false | true", options).1, 0);
+
+        options.pipefail = true;
+        assert_eq!(run_with_status("false | true", options).1, 1);
+        assert_eq!(run_with_status("true | true", options).1, 0);
+        assert_eq!(pipeline_status(&[2, 1, 0], true), 1);
+        assert_eq!(pipeline_status(&[2, 1, 0], false), 0);
+    }
+
+    #[test]
+    fn test_and_or_short_circuits() {
+        let dir = test_dir("and_or");
+        let file = dir.join("out");
+        let options = ShellOptions::new(false);
+
+        let status = run_with_status(&format!("false && echo no > {}", file.display()), options).1;
+        assert_eq!(status, 1);
+        assert!(!file.exists());
+
+        let status = run_with_status(&format!("false || echo yes > {}", file.display()), options).1;
+        assert_eq!(status, 0);
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "yes\n");
+
+        run(&format!("true && echo ok > {}", file.display()), options);
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "ok\n");
+
+        assert_eq!(run_with_status("true || false", options).1, 0);
+        assert_eq!(run_with_status("false || true && sh -c 'exit 3'", options).1, 3);
+    }
+
+    #[test]
+    fn test_shebangless_script_runs_with_sh() {
+        let dir = test_dir("shebangless");
+        let script = dir.join("script");
+        let file = dir.join("out");
+        std::fs::write(&script, "echo \"from sh: $1\"\n").unwrap();
+        std::fs::set_permissions(&script, std::os::unix::fs::PermissionsExt::from_mode(0o755)).unwrap();
+
+        let options = ShellOptions::new(false);
+        let status = run_with_status(&format!("{} arg > {}", script.display(), file.display()), options).1;
+        assert_eq!(status, 0);
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "from sh: arg\n");
+    }
+
+    #[test]
+    fn test_pipeline_terminates_when_reader_exits() {
+        let dir = test_dir("pipeline_terminates");
+        let file = dir.join("out");
+        let started = Instant::now();
+
+        run(
+            &format!("yes | head -1 | cat > {}", file.display()),
+            ShellOptions::new(false),
+        );
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "y\n");
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_rush_command_holds_running_command() {
+        let dir = test_dir("rush_command");
+        let file = dir.join("out");
+        let options = ShellOptions::new(false);
+
+        let source = format!("echo $RUSH_COMMAND > {}; false", file.display());
+        let ctx = run(&source, options);
+        let echoed = std::fs::read_to_string(&file).unwrap();
+        assert_eq!(echoed, format!("echo $RUSH_COMMAND > {}\n", file.display()));
+        assert_eq!(*ctx.current_command.lock().unwrap(), "false");
+    }
+
+    #[test]
+    fn test_background_command_is_not_waited_on() {
+        let started = Instant::now();
+        let ctx = run("sleep 0.2 &", ShellOptions::new(false));
+        assert!(started.elapsed() < Duration::from_millis(200));
+
+        // the job is still running after the shell went on
+        let pid = ctx.jobs.lock().unwrap()[&1].process_group_id;
+        assert_eq!(signal::kill(pid, None), Ok(()));
+
+        std::thread::sleep(Duration::from_millis(400));
+        JOBS_UPDATED.store(true, Ordering::Relaxed);
+        update_job_statuses(ctx.jobs.clone());
+        assert!(matches!(ctx.jobs.lock().unwrap()[&1].status, JobStatus::Done(Some(0))));
+    }
+
+    #[test]
+    fn test_foreground_command_gets_own_process_group() {
+        let output = test_dir("foreground_process_group").join("pgid");
+        _ = std::fs::remove_file(&output);
+        let source = format!("sh -c 'ps -o pgid= -p $$ > {}; exec sleep 5'", output.display());
+
+        // emulates Ctrl-C, which the terminal sends to its foreground process group, once the
+        // command reported which group that is
+        let interrupt = std::thread::spawn(move || {
+            let pgid = loop {
+                std::thread::sleep(Duration::from_millis(20));
+                let pgid = std::fs::read_to_string(&output).unwrap_or_default();
+                if let Ok(pgid) = pgid.trim().parse() {
+                    break Pid::from_raw(pgid);
+                }
+            };
+            // the test process would die with the interrupt if it shared the command's group
+            assert_ne!(pgid, nix::unistd::getpgrp());
+            signal::killpg(pgid, Signal::SIGINT).unwrap();
+        });
+
+        let started = Instant::now();
+        let (_, status) = run_with_status(&source, ShellOptions::new(true));
+        interrupt.join().unwrap();
+        assert_eq!(status, 128 + Signal::SIGINT as i32);
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_echo() {
+        let dir = test_dir("echo");
+        let file = dir.join("out");
+        let options = ShellOptions::new(false);
+
+        run(&format!("echo -n hi > {}", file.display()), options);
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "hi");
+
+        run(&format!("echo -e 'a\\tb\\n\\\\c\\q' > {}", file.display()), options);
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "a\tb\n\\c\\q\n");
+
+        run(&format!("echo hello world > {}", file.display()), options);
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "hello world\n");
+
+        run(&format!("echo -nx 'a\\tb' -n > {}", file.display()), options);
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "-nx a\\tb -n\n");
+
+        // like bash, echo has no `--`, it is printed like any other argument
+        run(&format!("echo -- -n > {}", file.display()), options);
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "-- -n\n");
+
+        run(&format!("echo piped | cat > {}", file.display()), options);
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "piped\n");
+    }
+
+    #[test]
+    fn test_exported_variables_reach_children() {
+        let dir = test_dir("export");
+        let file = dir.join("out");
+        let source = format!("export FOO=bar; sh -c 'echo $FOO$BAZ' > {}", file.display());
+
+        let ctx = run(&source, ShellOptions::new(false));
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "bar\n");
+
+        run_in(&ctx, &format!("BAZ=qux sh -c 'echo $FOO$BAZ' > {}", file.display()));
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "barqux\n");
+
+        run_in(
+            &ctx,
+            &format!("unset FOO; true | sh -c 'echo $FOO$BAZ' > {}", file.display()),
+        );
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "\n");
+
+        run_in(&ctx, "export PATH=/nonexistent");
+        assert_eq!(run_in(&ctx, "true"), 127);
+    }
+
+    #[test]
+    fn test_globbing() {
+        let dir = test_dir("globbing");
+        let file = dir.join("out");
+        std::fs::write(dir.join("a.txt"), "").unwrap();
+        std::fs::write(dir.join("b.txt"), "").unwrap();
+
+        let source = format!(
+            "echo {dir}/*.txt '{dir}/*.txt' > {out}",
+            dir = dir.display(),
+            out = file.display()
+        );
+        let ctx = run(&source, ShellOptions::new(false));
+        let expected = format!("{dir}/a.txt {dir}/b.txt {dir}/*.txt\n", dir = dir.display());
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), expected);
+
+        run_in(&ctx, "set -f");
+        run_in(&ctx, &format!("echo {}/*.txt > {}", dir.display(), file.display()));
+        assert_eq!(
+            std::fs::read_to_string(&file).unwrap(),
+            format!("{}/*.txt\n", dir.display())
+        );
+    }
+
+    #[test]
+    fn test_command_substitution() {
+        let dir = test_dir("command_substitution");
+        let file = dir.join("out");
+        std::fs::write(dir.join("words"), "one two\n").unwrap();
+
+        let source = format!(
+            "wc -w $(echo {dir}/words {dir}/words) > {out}",
+            dir = dir.display(),
+            out = file.display()
+        );
+        let ctx = run(&source, ShellOptions::new(false));
+        let output = std::fs::read_to_string(&file).unwrap();
+        let words = format!("{}/words", dir.display());
+        assert_eq!(
+            output.split_whitespace().collect::<Vec<_>>(),
+            ["2", &words, "2", &words, "4", "total"]
+        );
+
+        // the substitution runs in a copy of the shell, which keeps what it changes to itself
+        let source = format!("echo $(export FOO=bar; echo hi) \"[$FOO]\" > {}", file.display());
+        run_in(&ctx, &source);
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "hi []\n");
+    }
+
+    #[test]
+    fn test_history() {
+        let dir = test_dir("history_builtin");
+        let file = dir.join("out");
+
+        let ctx = run("true", ShellOptions::new(false));
+        ctx.history.lock().unwrap().push("echo one");
+        ctx.history.lock().unwrap().push("ls -la");
+
+        run_in(&ctx, &format!("history > {}", file.display()));
+        assert_eq!(
+            std::fs::read_to_string(&file).unwrap(),
+            "    1  echo one\n    2  ls -la\n"
+        );
+
+        run_in(&ctx, &format!("history 1 > {}", file.display()));
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "    2  ls -la\n");
+
+        assert_eq!(run_in(&ctx, "history x"), 1);
+    }
+
+    #[test]
+    fn test_negated_status() {
+        let options = ShellOptions::new(false);
+        assert_eq!(run_with_status("! false", options).1, 0);
+        assert_eq!(run_with_status("! true | false", options).1, 0);
+        assert_eq!(run_with_status("! true", options).1, 1);
+    }
+
+    #[test]
+    fn test_background_subshell_is_one_job() {
+        let (ctx, status) = run_with_status("(sleep 0.1; sh -c 'exit 3') &", ShellOptions::new(false));
+        assert_eq!(status, 0);
+
+        let jobs = ctx.jobs.lock().unwrap();
+        assert_eq!(jobs.len(), 1);
+        let job = &jobs[&1];
+        assert_eq!(job.command, "(sleep 0.1; sh -c 'exit 3')");
+        assert_eq!(Some(job.process_group_id), *ctx.last_background_pid.lock().unwrap());
+
+        // the body ran in the job's own process, which exits with the body's status
+        let status = waitpid(job.process_group_id, None).unwrap();
+        assert_eq!(status, WaitStatus::Exited(job.process_group_id, 3));
+    }
+
+    #[test]
+    fn test_nul_byte_argument_is_an_error() {
+        let options = ShellOptions::new(false);
+
+        let (_, status) = try_run("cat a\0b", options);
+        let error = status.unwrap_err();
+        assert!(matches!(&error, Error::InvalidArgument(arg) if arg == "a\0b"));
+        assert_eq!(error.to_string(), r#"invalid argument "a\0b": it contains a NUL byte"#);
+
+        assert!(matches!(
+            try_run("true | echo a\0b", options).1,
+            Err(Error::InvalidArgument(_))
+        ));
+        assert!(matches!(
+            try_run("exec echo a\0b", options).1,
+            Err(Error::InvalidArgument(_))
+        ));
+    }
+
+    #[test]
+    fn test_children_get_default_signal_handling() {
+        // an interactive shell ignores SIGINT, which only happens in a child here
+        let ForkResult::Parent { child } = (unsafe { nix::unistd::fork() }).unwrap() else {
+            unsafe { signal::signal(Signal::SIGINT, SigHandler::SigIgn) }.unwrap();
+            let options = ShellOptions::new(false);
+            let status = run_with_status("sh -c 'kill -INT $$'", options).1;
+            let piped = run_with_status("true | sh -c 'kill -INT $$'", options).1;
+            std::process::exit(if status == 130 && piped == 130 { 0 } else { 1 });
+        };
+
+        assert_eq!(waitpid(child, None).unwrap(), WaitStatus::Exited(child, 0));
+    }
+
+    #[test]
+    fn test_cd() {
+        let dir = test_dir("cd").canonicalize().unwrap();
+        std::fs::create_dir_all(dir.join("sub")).unwrap();
+        std::fs::create_dir_all(dir.join("-x")).unwrap();
+
+        // the working directory is shared by every test thread, so it only changes in a child
+        let ForkResult::Parent { child } = (unsafe { nix::unistd::fork() }).unwrap() else {
+            let changed = std::panic::catch_unwind(|| {
+                let source = format!("cd {}", dir.display());
+                let (ctx, status) = run_with_status(&source, ShellOptions::new(false));
+                assert_eq!(status, 0);
+                assert_eq!(std::env::current_dir().unwrap(), dir);
+
+                assert_eq!(run_in(&ctx, "cd sub"), 0);
+                assert_eq!(std::env::current_dir().unwrap(), dir.join("sub"));
+                let env = ctx.env.lock().unwrap().clone();
+                assert_eq!(env.get("PWD").unwrap(), dir.join("sub").display().to_string());
+                assert_eq!(env.get("OLDPWD").unwrap(), dir.display().to_string());
+
+                assert_eq!(run_in(&ctx, "cd -"), 0);
+                assert_eq!(std::env::current_dir().unwrap(), dir);
+
+                assert_eq!(run_in(&ctx, "cd -x"), 2);
+                assert_eq!(run_in(&ctx, "cd -- -x"), 0);
+                assert_eq!(std::env::current_dir().unwrap(), dir.join("-x"));
+                assert_eq!(run_in(&ctx, "cd .."), 0);
+
+                assert_eq!(run_in(&ctx, "cd ..; cd missing"), 1);
+                assert_eq!(std::env::current_dir().unwrap(), dir.parent().unwrap());
+            });
+            std::process::exit(if changed.is_ok() { 0 } else { 1 });
+        };
+
+        assert_eq!(waitpid(child, None).unwrap(), WaitStatus::Exited(child, 0));
+    }
+
+    #[test]
+    fn test_subshell() {
+        let dir = test_dir("subshell");
+        let file = dir.join("out");
+        let cwd = std::env::current_dir().unwrap();
+
+        let source = format!("(cd /tmp; export FOO=bar; pwd > {})", file.display());
+        let (ctx, status) = run_with_status(&source, ShellOptions::new(false));
+        assert_eq!(status, 0);
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "/tmp\n");
+        assert_eq!(std::env::current_dir().unwrap(), cwd);
+        assert_eq!(ctx.env.lock().unwrap().get("FOO"), None);
+
+        assert_eq!(run_in(&ctx, "(true; false)"), 1);
+        assert_eq!(run_in(&ctx, "(false; true) && (true)"), 0);
+    }
+
+    #[test]
+    fn test_osc7() {
+        let dir = test_dir("osc7").canonicalize().unwrap();
+        let file = dir.join("out");
+        std::fs::create_dir_all(dir.join("a b%")).unwrap();
+        let host = nix::unistd::gethostname().unwrap().into_string().unwrap();
+
+        // the subshell keeps the `cd` away from the working directory the tests share
+        let source = format!(
+            "(cd '{dir}/a b%' > {out}; set -o osc7; cd '{dir}/a b%' >> {out})",
+            dir = dir.display(),
+            out = file.display()
+        );
+        run(&source, ShellOptions::new(false));
+        assert_eq!(
+            std::fs::read_to_string(&file).unwrap(),
+            format!("\x1b]7;file://{host}{}/a%20b%25\x1b\\", dir.display())
+        );
+        assert_eq!(osc7("host", "/tmp/é"), "\x1b]7;file://host/tmp/%C3%A9\x1b\\");
+    }
+
+    #[test]
+    fn test_autocd() {
+        let dir = test_dir("autocd").canonicalize().unwrap();
+        std::fs::create_dir_all(dir.join("sub")).unwrap();
+
+        let ForkResult::Parent { child } = (unsafe { nix::unistd::fork() }).unwrap() else {
+            let changed = std::panic::catch_unwind(|| {
+                let source = format!("cd {}", dir.display());
+                let ctx = run(&source, ShellOptions::new(false));
+                assert_eq!(run_in(&ctx, "sub"), 127);
+                assert_eq!(std::env::current_dir().unwrap(), dir);
+
+                run_in(&ctx, "set -o autocd");
+                assert_eq!(run_in(&ctx, "sub"), 0);
+                assert_eq!(std::env::current_dir().unwrap(), dir.join("sub"));
+                assert_eq!(run_in(&ctx, ".."), 0);
+                assert_eq!(std::env::current_dir().unwrap(), dir);
+                assert_eq!(run_in(&ctx, &format!("{}/sub", dir.display())), 0);
+                assert_eq!(std::env::current_dir().unwrap(), dir.join("sub"));
+
+                // with arguments it is still a command
+                assert_eq!(run_in(&ctx, ".. arg"), 127);
+            });
+            std::process::exit(if changed.is_ok() { 0 } else { 1 });
+        };
+
+        assert_eq!(waitpid(child, None).unwrap(), WaitStatus::Exited(child, 0));
+    }
+
+    #[test]
+    fn test_pwd() {
+        let dir = test_dir("pwd").canonicalize().unwrap();
+        let file = dir.join("out");
+        std::fs::create_dir_all(dir.join("real")).unwrap();
+        _ = std::os::unix::fs::symlink(dir.join("real"), dir.join("link"));
+
+        let ForkResult::Parent { child } = (unsafe { nix::unistd::fork() }).unwrap() else {
+            let printed = std::panic::catch_unwind(|| {
+                let source = format!("cd {}", dir.display());
+                let ctx = run(&source, ShellOptions::new(false));
+                ctx.env
+                    .lock()
+                    .unwrap()
+                    .set("PWD", dir.join("link").display().to_string());
+                nix::unistd::chdir(&dir.join("link")).unwrap();
+
+                run_in(&ctx, &format!("pwd > {}", file.display()));
+                assert_eq!(
+                    std::fs::read_to_string(&file).unwrap(),
+                    format!("{}\n", dir.join("link").display())
+                );
+
+                run_in(&ctx, &format!("pwd -P > {}", file.display()));
+                assert_eq!(
+                    std::fs::read_to_string(&file).unwrap(),
+                    format!("{}\n", dir.join("real").display())
+                );
+
+                run_in(&ctx, &format!("cd /tmp; pwd > {}", file.display()));
+                assert_eq!(std::fs::read_to_string(&file).unwrap(), "/tmp\n");
+            });
+            std::process::exit(if printed.is_ok() { 0 } else { 1 });
+        };
+
+        assert_eq!(waitpid(child, None).unwrap(), WaitStatus::Exited(child, 0));
+    }
+
+    #[test]
+    fn test_last_status() {
+        let dir = test_dir("last_status");
+        let output = dir.join("status");
+        let options = ShellOptions::new(false);
+
+        let (ctx, status) = run_with_status("false", options);
+        assert_eq!(status, 1);
+        assert_eq!(ctx.special_parameter("?"), Some(String::from("1")));
+
+        run(&format!("false; sh -c 'echo $0 > {}' $?", output.display()), options);
+        assert_eq!(std::fs::read_to_string(&output).unwrap(), "1\n");
+
+        run(&format!("true; sh -c 'echo $0 > {}' ${{?}}", output.display()), options);
+        assert_eq!(std::fs::read_to_string(&output).unwrap(), "0\n");
+    }
+
+    #[test]
+    fn test_output_redirection() {
+        let dir = test_dir("output_redirection");
+        let file = dir.join("out");
+        let options = ShellOptions::new(false);
+
+        run(&format!("echo hi > {}", file.display()), options);
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "hi\n");
+
+        run(
+            &format!("echo again >> {}; echo more 1>>{}", file.display(), file.display()),
+            options,
+        );
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "hi\nagain\nmore\n");
+
+        run(&format!("echo truncated >{}", file.display()), options);
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "truncated\n");
+
+        let missing = dir.join("missing").join("out");
+        let status = run_with_status(&format!("echo hi > {}", missing.display()), options).1;
+        assert_eq!(status, 1);
+    }
+
+    #[test]
+    fn test_input_redirection() {
+        let dir = test_dir("input_redirection");
+        let input = dir.join("in");
+        let output = dir.join("out");
+        std::fs::write(&input, "a\nb\nc\n").unwrap();
+        let options = ShellOptions::new(false);
+
+        run(&format!("wc -l < {} > {}", input.display(), output.display()), options);
+        assert_eq!(std::fs::read_to_string(&output).unwrap().trim(), "3");
+
+        run(
+            &format!("echo ignored | wc -l < {} > {}", input.display(), output.display()),
+            options,
+        );
+        assert_eq!(std::fs::read_to_string(&output).unwrap().trim(), "3");
+
+        run(
+            &format!("cat < {} | wc -l > {}", input.display(), output.display()),
+            options,
+        );
+        assert_eq!(std::fs::read_to_string(&output).unwrap().trim(), "3");
+
+        let missing = dir.join("missing");
+        let status = run_with_status(
+            &format!("wc -l < {} > {}", missing.display(), output.display()),
+            options,
+        )
+        .1;
+        assert_eq!(status, 1);
+        assert_eq!(std::fs::read_to_string(&output).unwrap().trim(), "3");
+    }
+
+    #[test]
+    fn test_kill_cont_marks_job_running() {
+        let ctx = run("sleep 5 &", ShellOptions::new(false));
+        let pid = ctx.jobs.lock().unwrap()[&1].process_group_id;
+
+        assert_eq!(run_in(&ctx, "kill -STOP %1"), 0);
+        let status = waitpid(pid, Some(WaitPidFlag::WUNTRACED)).unwrap();
+        assert!(matches!(status, WaitStatus::Stopped(_, Signal::SIGSTOP)));
+        ctx.jobs.lock().unwrap().get_mut(&1).unwrap().status = JobStatus::Stopped;
+
+        assert_eq!(run_in(&ctx, "kill -CONT %1"), 0);
+        assert!(matches!(ctx.jobs.lock().unwrap()[&1].status, JobStatus::Running));
+        let status = waitpid(pid, Some(WaitPidFlag::WCONTINUED)).unwrap();
+        assert_eq!(status, WaitStatus::Continued(pid));
+
+        assert_eq!(run_in(&ctx, "kill %2"), 1);
+        assert_eq!(run_in(&ctx, "kill -BOGUS %1"), 2);
+        assert_eq!(run_in(&ctx, "kill -s KILL %1"), 0);
+        assert_eq!(
+            waitpid(pid, None).unwrap(),
+            WaitStatus::Signaled(pid, Signal::SIGKILL, false)
+        );
+    }
+
+    #[test]
+    fn test_stopped_pipeline_becomes_job() {
+        let (ctx, status) = run_with_status("sh -c 'kill -STOP $$' | true", ShellOptions::new(true));
+        assert_eq!(status, 0);
+        assert_eq!(*ctx.pipe_status.lock().unwrap(), [128 + Signal::SIGTSTP as i32, 0]);
+
+        let job = ctx.jobs.lock().unwrap()[&1].clone();
+        assert!(matches!(job.status, JobStatus::Stopped));
+        assert!(job.is_foreground);
+        assert_eq!(job.command, "sh -c 'kill -STOP $$' | true");
+
+        signal::killpg(job.process_group_id, Signal::SIGKILL).unwrap();
+        waitpid(job.process_group_id, None).unwrap();
+    }
 }
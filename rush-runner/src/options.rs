@@ -0,0 +1,157 @@
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ShellOptions {
+    /// Job control: background jobs and pipelines get their own process groups.
+    pub monitor: bool,
+    /// A pipeline fails with the status of its rightmost failing stage, not just its last one.
+    pub pipefail: bool,
+    /// Filename expansion is off, glob characters are passed to commands literally.
+    pub noglob: bool,
+    /// Input lines are echoed to stderr as they are read, before anything runs.
+    pub verbose: bool,
+    /// Commands are echoed to stderr after expansion, prefixed with `+`.
+    pub xtrace: bool,
+    /// A directory name on its own is a `cd` into it, when no program goes by that name.
+    pub autocd: bool,
+    /// `cd` reports the new working directory to the terminal with an OSC 7 escape sequence.
+    pub osc7: bool,
+}
+
+impl ShellOptions {
+    /// Options a shell starts with, job control is only enabled for interactive shells.
+    pub fn new(is_interactive: bool) -> Self {
+        Self {
+            monitor: is_interactive,
+            pipefail: false,
+            noglob: false,
+            verbose: false,
+            xtrace: false,
+            autocd: false,
+            osc7: false,
+        }
+    }
+
+    fn get_mut(&mut self, name: &str) -> Option<&mut bool> {
+        match name {
+            "autocd" => Some(&mut self.autocd),
+            "monitor" => Some(&mut self.monitor),
+            "pipefail" => Some(&mut self.pipefail),
+            "noglob" => Some(&mut self.noglob),
+            "osc7" => Some(&mut self.osc7),
+            "verbose" => Some(&mut self.verbose),
+            "xtrace" => Some(&mut self.xtrace),
+            _ => None,
+        }
+    }
+
+    fn iter(&self) -> impl Iterator<Item = (&'static str, bool)> {
+        [
+            ("autocd", self.autocd),
+            ("monitor", self.monitor),
+            ("noglob", self.noglob),
+            ("osc7", self.osc7),
+            ("pipefail", self.pipefail),
+            ("verbose", self.verbose),
+            ("xtrace", self.xtrace),
+        ]
+        .into_iter()
+    }
+}
+
+/// `set -o name`, `set +o name` and their short flags, `set -o` alone lists every option. A `--`
+/// ends the options.
+pub(crate) fn set(options: &mut ShellOptions, args: &[&str]) -> i32 {
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        // `--` ends the options; what follows would be positional parameters, which the shell
+        // doesn't have
+        if *arg == "--" {
+            return match args.next() {
+                Some(arg) => {
+                    eprintln!("rush: set: {arg}: positional parameters are not supported");
+                    2
+                }
+                None => 0,
+            };
+        }
+
+        let enable = match arg.chars().next() {
+            Some('-') => true,
+            Some('+') => false,
+            _ => {
+                eprintln!("rush: set: {arg}: invalid option");
+                return 2;
+            }
+        };
+
+        let name = match arg.get(1..) {
+            Some("o") => match args.next() {
+                Some(name) => *name,
+                None => {
+                    options
+                        .iter()
+                        .for_each(|(name, value)| println!("{name:<15} {}", if value { "on" } else { "off" }));
+                    continue;
+                }
+            },
+            Some("m") => "monitor",
+            Some("f") => "noglob",
+            Some("v") => "verbose",
+            Some("x") => "xtrace",
+            _ => {
+                eprintln!("rush: set: {arg}: invalid option");
+                return 2;
+            }
+        };
+
+        match options.get_mut(name) {
+            Some(option) => *option = enable,
+            None => {
+                eprintln!("rush: set: {name}: invalid option name");
+                return 1;
+            }
+        }
+    }
+
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_set_monitor() {
+        let mut options = ShellOptions::new(false);
+
+        set(&mut options, &["-o", "monitor"]);
+        assert!(options.monitor);
+
+        set(&mut options, &["+m"]);
+        assert!(!options.monitor);
+
+        assert_eq!(set(&mut options, &["-o", "pipefail"]), 0);
+        assert!(options.pipefail);
+
+        set(&mut options, &["-f"]);
+        assert!(options.noglob);
+        set(&mut options, &["+o", "noglob"]);
+        assert!(!options.noglob);
+
+        set(&mut options, &["-v", "-x"]);
+        assert!(options.verbose && options.xtrace);
+
+        assert_eq!(set(&mut options, &["-o", "bogus"]), 1);
+        assert_eq!(set(&mut options, &["bogus"]), 2);
+    }
+
+    #[test]
+    fn test_set_end_of_options() {
+        let mut options = ShellOptions::new(false);
+
+        assert_eq!(set(&mut options, &["-x", "--"]), 0);
+        assert!(options.xtrace);
+        assert_eq!(set(&mut options, &["--", "+x"]), 2);
+        assert!(options.xtrace);
+    }
+}
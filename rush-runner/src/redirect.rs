@@ -0,0 +1,100 @@
+use std::io::Write;
+use std::os::fd::{AsRawFd, BorrowedFd, FromRawFd, IntoRawFd, OwnedFd, RawFd};
+
+use nix::errno::Errno;
+use nix::fcntl::{FcntlArg, OFlag};
+use nix::sys::stat::Mode;
+use rush_parser::{Redirect, RedirectOp};
+
+use crate::result::{Error, Result};
+
+/// Applies `redirects` to the current process in the order they were written, each one seeing
+/// the fds as the ones before it left them, so `3>&1 1>log` keeps fd 3 on the old stdout.
+pub(crate) fn apply(redirects: &[Redirect], source: &str) -> Result<()> {
+    for redirect in redirects {
+        let target = redirect.target.value(source);
+        let error = |source: Errno| Error::Redirect {
+            target: target.to_string(),
+            source,
+        };
+
+        let fd = redirect
+            .fd_number(source)
+            .and_then(|fd| RawFd::try_from(fd).ok())
+            .ok_or(error(Errno::EBADF))?;
+
+        match redirect.op {
+            RedirectOp::DuplicateOutput => {
+                let target_fd = target.parse::<RawFd>().map_err(|_| error(Errno::EBADF))?;
+                duplicate(target_fd, fd).map_err(error)?;
+            }
+            op => {
+                let flags = match op {
+                    RedirectOp::Output => OFlag::O_WRONLY | OFlag::O_CREAT | OFlag::O_TRUNC,
+                    RedirectOp::Append => OFlag::O_WRONLY | OFlag::O_CREAT | OFlag::O_APPEND,
+                    _ => OFlag::O_RDONLY,
+                };
+                let file = nix::fcntl::open(target, flags, Mode::from_bits_truncate(0o644)).map_err(error)?;
+
+                // open hands out the lowest free fd, which can be the very fd being redirected
+                if file.as_raw_fd() == fd {
+                    _ = file.into_raw_fd();
+                    continue;
+                }
+                duplicate(file.as_raw_fd(), fd).map_err(error)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Copies of the fds a builtin's redirections replaced, put back when dropped so the shell's own
+/// fds are unchanged once the builtin is done.
+pub(crate) struct SavedFds(Vec<(RawFd, Option<OwnedFd>)>);
+
+/// Applies `redirects` to the shell itself for the lifetime of the returned [`SavedFds`].
+pub(crate) fn apply_saved(redirects: &[Redirect], source: &str) -> Result<SavedFds> {
+    let mut saved = SavedFds(vec![]);
+    for fd in redirects.iter().filter_map(|redirect| redirect.fd_number(source)) {
+        let Ok(fd) = RawFd::try_from(fd) else { continue };
+        if saved.0.iter().any(|(saved_fd, _)| *saved_fd == fd) {
+            continue;
+        }
+
+        // a copy above the fds users redirect, `None` when the fd wasn't open to begin with
+        let copy = nix::fcntl::fcntl(unsafe { BorrowedFd::borrow_raw(fd) }, FcntlArg::F_DUPFD_CLOEXEC(10))
+            .ok()
+            .map(|copy| unsafe { OwnedFd::from_raw_fd(copy) });
+        saved.0.push((fd, copy));
+    }
+
+    // on failure the redirections applied so far are undone as `saved` drops
+    apply(redirects, source)?;
+    Ok(saved)
+}
+
+impl Drop for SavedFds {
+    fn drop(&mut self) {
+        // output the builtin buffered still belongs to the redirected fds
+        _ = std::io::stdout().flush();
+        _ = std::io::stderr().flush();
+
+        for (fd, copy) in self.0.drain(..).rev() {
+            match copy {
+                Some(copy) => _ = duplicate(copy.as_raw_fd(), fd),
+                None => _ = nix::unistd::close(fd),
+            }
+        }
+    }
+}
+
+/// Makes `to` refer to whatever `from` does, leaving both open.
+pub(crate) fn duplicate(from: RawFd, to: RawFd) -> nix::Result<()> {
+    let from = unsafe { BorrowedFd::borrow_raw(from) };
+    // `to` is only owned for dup2's signature, it has to stay open afterwards
+    let mut to = unsafe { OwnedFd::from_raw_fd(to) };
+    let result = nix::unistd::dup2(from, &mut to);
+    _ = to.into_raw_fd();
+    result
+}
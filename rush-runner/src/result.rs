@@ -4,4 +4,10 @@ pub type Result<T, E = Error> = std::result::Result<T, E>;
 pub enum Error {
     #[error("{0:?}")]
     Unix(#[from] nix::Error),
+    #[error("{target}: {}", .source.desc())]
+    Redirect { target: String, source: nix::Error },
+    #[error("{0}: no such job")]
+    NoSuchJob(String),
+    #[error("invalid argument {0:?}: it contains a NUL byte")]
+    InvalidArgument(String),
 }
@@ -0,0 +1,74 @@
+use std::time::Duration;
+
+use crate::result::{Error, Result};
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Args {
+    /// `--timeout SECONDS`, foreground commands running longer than this are killed.
+    pub command_timeout: Option<Duration>,
+    /// `-c STRING`, a command string run instead of reading commands from stdin.
+    pub command: Option<String>,
+    /// `-v`, the shell starts with the `verbose` option set.
+    pub verbose: bool,
+}
+
+pub fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Args> {
+    let mut parsed = Args::default();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--timeout" => {
+                let seconds = args
+                    .next()
+                    .ok_or(Error::InvalidArgs("--timeout requires a value".into()))?;
+                let seconds = seconds
+                    .parse::<f64>()
+                    .ok()
+                    .and_then(|seconds| Duration::try_from_secs_f64(seconds).ok())
+                    .ok_or(Error::InvalidArgs(format!("invalid timeout: {seconds}")))?;
+                parsed.command_timeout = Some(seconds);
+            }
+            "-v" => parsed.verbose = true,
+            "-c" => {
+                let command = args.next().ok_or(Error::InvalidArgs("-c requires a value".into()))?;
+                parsed.command = Some(command);
+            }
+            _ => return Err(Error::InvalidArgs(format!("unknown option: {arg}"))),
+        }
+    }
+
+    Ok(parsed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Args> {
+        parse_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn test_parse_timeout() {
+        let args = parse(&["--timeout", "1.5"]).unwrap();
+        assert_eq!(args.command_timeout, Some(Duration::from_millis(1500)));
+
+        assert!(parse(&["--timeout"]).is_err());
+        assert!(parse(&["--timeout", "-1"]).is_err());
+        assert!(parse(&["--bogus"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_command() {
+        let args = parse(&["-c", "echo hi; echo bye"]).unwrap();
+        assert_eq!(args.command.as_deref(), Some("echo hi; echo bye"));
+
+        assert!(parse(&["-c"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_verbose() {
+        assert!(!parse(&[]).unwrap().verbose);
+        assert!(parse(&["-v", "-c", "true"]).unwrap().verbose);
+    }
+}
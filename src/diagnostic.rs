@@ -0,0 +1,76 @@
+use rush_lexer::Span;
+
+const ERROR_STYLE: &str = "\x1b[1;31m";
+const GUTTER_STYLE: &str = "\x1b[1;34m";
+const RESET_STYLE: &str = "\x1b[0m";
+
+/// Renders a parse error as its message followed by the offending source line, with carets under
+/// the span the error points at. Colors are only used when `color` is set.
+pub fn render_diagnostic(source: &str, error: &rush_parser::Error, color: bool) -> String {
+    let style = |style: &'static str| if color { style } else { "" };
+    let (error_style, gutter_style, reset) = (style(ERROR_STYLE), style(GUTTER_STYLE), style(RESET_STYLE));
+
+    let mut rendered = format!("{error_style}error{reset}: {error}\n");
+    let Some(span) = error.span() else { return rendered };
+
+    // an unexpected end of input points past trailing newlines, keep it on the last line instead
+    let start = (*span.start).min(source.trim_end().len());
+    let end = (*span.end).clamp(start, source.len());
+    let line_start = source[..start].rfind('\n').map_or(0, |idx| idx + 1);
+    let line_end = source[start..].find('\n').map_or(source.len(), |idx| start + idx);
+
+    let line = &source[line_start..line_end];
+    let (line_number, column) = Span::new(start.into(), end.into()).line_col(source);
+    let width = source[start..end.min(line_end)].chars().count().max(1);
+
+    let gutter = " ".repeat(line_number.to_string().len());
+    let padding = " ".repeat(column - 1);
+    let carets = "^".repeat(width);
+
+    rendered.push_str(&format!(
+        "{gutter}{gutter_style}-->{reset} line {line_number}, column {column}\n"
+    ));
+    rendered.push_str(&format!("{gutter} {gutter_style}|{reset}\n"));
+    rendered.push_str(&format!("{gutter_style}{line_number} |{reset} {line}\n"));
+    rendered.push_str(&format!(
+        "{gutter} {gutter_style}|{reset} {padding}{error_style}{carets}{reset}\n"
+    ));
+    rendered
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn diagnostic(source: &str) -> String {
+        let tokens = rush_lexer::Lexer::new(source).lex();
+        let error = rush_parser::Parser::new().parse(tokens).unwrap_err();
+        render_diagnostic(source, &error, false)
+    }
+
+    #[test]
+    fn test_render_diagnostic() {
+        let expected = [
+            "error: Expected command, found Pipe",
+            " --> line 1, column 8",
+            "  |",
+            "1 | echo | | wc",
+            "  |        ^",
+            "",
+        ];
+        assert_eq!(diagnostic("echo | | wc"), expected.join("\n"));
+    }
+
+    #[test]
+    fn test_render_diagnostic_at_end_of_input() {
+        let expected = [
+            "error: Unexpected end of input",
+            " --> line 2, column 8",
+            "  |",
+            "2 | wc -l |",
+            "  |        ^",
+            "",
+        ];
+        assert_eq!(diagnostic("ls;\nwc -l |\n"), expected.join("\n"));
+    }
+}
@@ -0,0 +1,343 @@
+use std::io::Write;
+use std::os::fd::BorrowedFd;
+
+use nix::errno::Errno;
+use nix::sys::termios::{self, InputFlags, LocalFlags, SetArg, SpecialCharacterIndices, Termios};
+
+use crate::input::LineInput;
+use crate::result::Result;
+
+/// A key press decoded from the bytes a terminal sends in raw mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Key {
+    Char(char),
+    Left,
+    Right,
+    Home,
+    End,
+    Backspace,
+    Delete,
+    Enter,
+    /// Ctrl-C, which gives up on the line.
+    Interrupt,
+    /// Ctrl-D, which ends the input on an empty line.
+    Eof,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+enum DecoderState {
+    #[default]
+    Ground,
+    /// After `ESC`.
+    Escape,
+    /// After `ESC [`, with the number of a `ESC [ n ~` sequence read so far.
+    Csi(u32),
+    /// After `ESC O`, which some terminals send for Home and End.
+    Ss3,
+    /// In a UTF-8 sequence, with the bytes read so far and how many it takes.
+    Utf8([u8; 4], usize, usize),
+}
+
+/// Turns the bytes of raw terminal input into keys, one byte at a time. Sequences it doesn't know
+/// are dropped whole instead of inserting their bytes into the line.
+#[derive(Debug, Default)]
+pub struct KeyDecoder {
+    state: DecoderState,
+}
+
+impl KeyDecoder {
+    /// Feeds the next byte, returning the key it completes, if any.
+    pub fn feed(&mut self, byte: u8) -> Option<Key> {
+        let (state, key) = match (self.state, byte) {
+            (DecoderState::Ground, 0x1b) => (DecoderState::Escape, None),
+            (DecoderState::Ground, byte) => self.ground(byte),
+
+            (DecoderState::Escape, b'[') => (DecoderState::Csi(0), None),
+            (DecoderState::Escape, b'O') => (DecoderState::Ss3, None),
+            (DecoderState::Escape, _) => (DecoderState::Ground, None),
+
+            (DecoderState::Csi(n), digit @ b'0'..=b'9') => (
+                DecoderState::Csi(n.saturating_mul(10).saturating_add((digit - b'0') as u32)),
+                None,
+            ),
+            (DecoderState::Csi(_), b';') => (self.state, None),
+            (DecoderState::Csi(_), b'C') => (DecoderState::Ground, Some(Key::Right)),
+            (DecoderState::Csi(_), b'D') => (DecoderState::Ground, Some(Key::Left)),
+            (DecoderState::Csi(_), b'H') => (DecoderState::Ground, Some(Key::Home)),
+            (DecoderState::Csi(_), b'F') => (DecoderState::Ground, Some(Key::End)),
+            (DecoderState::Csi(n), b'~') => (
+                DecoderState::Ground,
+                match n {
+                    1 | 7 => Some(Key::Home),
+                    4 | 8 => Some(Key::End),
+                    3 => Some(Key::Delete),
+                    _ => None,
+                },
+            ),
+            // any other final byte ends a sequence that has no meaning here, like the up arrow
+            (DecoderState::Csi(_), 0x40..=0x7e) => (DecoderState::Ground, None),
+            (DecoderState::Csi(_), _) => (self.state, None),
+
+            (DecoderState::Ss3, b'H') => (DecoderState::Ground, Some(Key::Home)),
+            (DecoderState::Ss3, b'F') => (DecoderState::Ground, Some(Key::End)),
+            (DecoderState::Ss3, _) => (DecoderState::Ground, None),
+
+            (DecoderState::Utf8(mut bytes, len, needed), 0x80..=0xbf) => {
+                bytes[len] = byte;
+                match len + 1 == needed {
+                    true => {
+                        let ch = std::str::from_utf8(&bytes[..needed])
+                            .ok()
+                            .and_then(|s| s.chars().next());
+                        (
+                            DecoderState::Ground,
+                            Some(Key::Char(ch.unwrap_or(char::REPLACEMENT_CHARACTER))),
+                        )
+                    }
+                    false => (DecoderState::Utf8(bytes, len + 1, needed), None),
+                }
+            }
+            // a sequence cut short is dropped, and the byte that cut it starts over
+            (DecoderState::Utf8(..), byte) => {
+                self.state = DecoderState::Ground;
+                return self.feed(byte);
+            }
+        };
+
+        self.state = state;
+        key
+    }
+
+    fn ground(&self, byte: u8) -> (DecoderState, Option<Key>) {
+        let key = match byte {
+            b'\r' | b'\n' => Key::Enter,
+            0x7f | 0x08 => Key::Backspace,
+            0x03 => Key::Interrupt,
+            0x04 => Key::Eof,
+            0x01 => Key::Home,
+            0x05 => Key::End,
+            0x02 => Key::Left,
+            0x06 => Key::Right,
+            0x20..=0x7e => Key::Char(byte as char),
+            0xc2..=0xf4 => {
+                let needed = match byte {
+                    0xc2..=0xdf => 2,
+                    0xe0..=0xef => 3,
+                    _ => 4,
+                };
+                return (DecoderState::Utf8([byte, 0, 0, 0], 1, needed), None);
+            }
+            0x80..=0xff => Key::Char(char::REPLACEMENT_CHARACTER),
+            // other control characters have nothing to do yet
+            _ => return (DecoderState::Ground, None),
+        };
+        (DecoderState::Ground, Some(key))
+    }
+}
+
+/// The line being edited and the cursor position in it, counted in characters.
+#[derive(Debug, Default)]
+pub struct LineBuffer {
+    chars: Vec<char>,
+    cursor: usize,
+}
+
+impl LineBuffer {
+    /// Applies an editing key, returning whether the line or the cursor changed.
+    pub fn apply(&mut self, key: Key) -> bool {
+        match key {
+            Key::Char(ch) => {
+                self.chars.insert(self.cursor, ch);
+                self.cursor += 1;
+            }
+            Key::Backspace if self.cursor > 0 => {
+                self.cursor -= 1;
+                self.chars.remove(self.cursor);
+            }
+            Key::Delete if self.cursor < self.chars.len() => _ = self.chars.remove(self.cursor),
+            Key::Left if self.cursor > 0 => self.cursor -= 1,
+            Key::Right if self.cursor < self.chars.len() => self.cursor += 1,
+            Key::Home => self.cursor = 0,
+            Key::End => self.cursor = self.chars.len(),
+            _ => return false,
+        }
+        true
+    }
+
+    pub fn len(&self) -> usize {
+        self.chars.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.chars.is_empty()
+    }
+
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    pub fn text(&self) -> String {
+        self.chars.iter().collect()
+    }
+}
+
+/// Puts the terminal in raw mode for as long as it lives, the settings it had are restored when it
+/// is dropped, whichever way the read ends.
+pub struct RawMode<'fd> {
+    fd: BorrowedFd<'fd>,
+    original: Termios,
+}
+
+impl<'fd> RawMode<'fd> {
+    pub fn enable(fd: BorrowedFd<'fd>) -> Result<Self> {
+        let original = termios::tcgetattr(fd).map_err(std::io::Error::from)?;
+
+        let mut raw = original.clone();
+        // output processing stays on, so `\n` still starts a new line
+        raw.local_flags &= !(LocalFlags::ICANON | LocalFlags::ECHO | LocalFlags::ISIG | LocalFlags::IEXTEN);
+        raw.input_flags &= !(InputFlags::IXON | InputFlags::ICRNL);
+        raw.control_chars[SpecialCharacterIndices::VMIN as usize] = 1;
+        raw.control_chars[SpecialCharacterIndices::VTIME as usize] = 0;
+        termios::tcsetattr(fd, SetArg::TCSADRAIN, &raw).map_err(std::io::Error::from)?;
+
+        Ok(Self { fd, original })
+    }
+}
+
+impl Drop for RawMode<'_> {
+    fn drop(&mut self) {
+        _ = termios::tcsetattr(self.fd, SetArg::TCSADRAIN, &self.original);
+    }
+}
+
+/// Reads a line from the terminal in `raw_mode` with the cursor keys, Home, End, Backspace and
+/// Delete editing it in place. Ctrl-C abandons the line, and Ctrl-D on an empty line ends the
+/// input.
+pub fn read_line(raw_mode: &RawMode<'_>) -> Result<LineInput> {
+    let mut stdout = std::io::stdout();
+    let mut decoder = KeyDecoder::default();
+    let mut line = LineBuffer::default();
+
+    loop {
+        let mut byte = [0];
+        match nix::unistd::read(raw_mode.fd, &mut byte) {
+            Ok(0) if line.is_empty() => return Ok(LineInput::Eof),
+            Ok(0) => break,
+            Ok(_) => {}
+            // a signal like SIGCHLD interrupted the read, nothing was lost
+            Err(Errno::EINTR) => continue,
+            Err(e) => return Err(std::io::Error::from(e).into()),
+        }
+
+        let Some(key) = decoder.feed(byte[0]) else { continue };
+        match key {
+            Key::Enter => break,
+            Key::Interrupt => {
+                writeln!(stdout, "^C")?;
+                stdout.flush()?;
+                return Ok(LineInput::Interrupted);
+            }
+            Key::Eof if line.is_empty() => return Ok(LineInput::Eof),
+            // typing at the end of the line only needs the character echoed
+            Key::Char(ch) if line.cursor() == line.len() => {
+                line.apply(key);
+                write!(stdout, "{ch}")?;
+                stdout.flush()?;
+            }
+            key => {
+                let cursor = line.cursor();
+                if line.apply(key) {
+                    redraw(&mut stdout, &line, cursor)?;
+                }
+            }
+        }
+    }
+
+    writeln!(stdout)?;
+    stdout.flush()?;
+    Ok(LineInput::Line(line.text() + "\n"))
+}
+
+/// Rewrites the line after the prompt, from the column the cursor was at before the edit.
+fn redraw(out: &mut impl Write, line: &LineBuffer, previous_cursor: usize) -> Result<()> {
+    if previous_cursor > 0 {
+        write!(out, "\x1b[{previous_cursor}D")?;
+    }
+    write!(out, "{}\x1b[K", line.text())?;
+
+    let after_cursor = line.len() - line.cursor();
+    if after_cursor > 0 {
+        write!(out, "\x1b[{after_cursor}D")?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode(bytes: &[u8]) -> Vec<Key> {
+        let mut decoder = KeyDecoder::default();
+        bytes.iter().filter_map(|byte| decoder.feed(*byte)).collect()
+    }
+
+    fn edit(bytes: &[u8]) -> (String, usize) {
+        let mut line = LineBuffer::default();
+        decode(bytes).into_iter().for_each(|key| _ = line.apply(key));
+        (line.text(), line.cursor())
+    }
+
+    #[test]
+    fn test_decode_keys() {
+        assert_eq!(decode(b"ab\r"), [Key::Char('a'), Key::Char('b'), Key::Enter]);
+        assert_eq!(
+            decode(b"\x1b[D\x1b[C\x1b[H\x1b[F"),
+            [Key::Left, Key::Right, Key::Home, Key::End]
+        );
+        assert_eq!(
+            decode(b"\x1bOH\x1bOF\x1b[1~\x1b[4~"),
+            [Key::Home, Key::End, Key::Home, Key::End]
+        );
+        assert_eq!(
+            decode(b"\x7f\x1b[3~\x03\x04"),
+            [Key::Backspace, Key::Delete, Key::Interrupt, Key::Eof]
+        );
+        assert_eq!(decode("é日".as_bytes()), [Key::Char('é'), Key::Char('日')]);
+    }
+
+    #[test]
+    fn test_decode_drops_unknown_sequences() {
+        // up arrow, a modified right arrow and an unknown `~` sequence
+        assert_eq!(
+            decode(b"\x1b[Aa\x1b[1;5Cb\x1b[15~c"),
+            [Key::Char('a'), Key::Right, Key::Char('b'), Key::Char('c')]
+        );
+        assert_eq!(
+            decode(b"\xc3a\x80b"),
+            [Key::Char('a'), Key::Char(char::REPLACEMENT_CHARACTER), Key::Char('b')]
+        );
+    }
+
+    #[test]
+    fn test_edit_line() {
+        assert_eq!(edit(b"echo hi"), (String::from("echo hi"), 7));
+        assert_eq!(edit(b"eho\x1b[D\x1b[Dc"), (String::from("echo"), 2));
+        assert_eq!(edit(b"echo\x1b[H#\x1b[F!"), (String::from("#echo!"), 6));
+        assert_eq!(edit(b"abc\x1b[D\x7f"), (String::from("ac"), 1));
+        assert_eq!(edit(b"abc\x1b[H\x1b[3~\x7f\x1b[D"), (String::from("bc"), 0));
+        assert_eq!(edit("日本\x1b[D語".as_bytes()), (String::from("日語本"), 2));
+    }
+
+    #[test]
+    fn test_redraw() {
+        let mut line = LineBuffer::default();
+        decode(b"ac\x1b[D").into_iter().for_each(|key| _ = line.apply(key));
+
+        let mut out = vec![];
+        let cursor = line.cursor();
+        line.apply(Key::Char('b'));
+        redraw(&mut out, &line, cursor).unwrap();
+        assert_eq!(out, b"\x1b[1Dabc\x1b[K\x1b[1D");
+    }
+}
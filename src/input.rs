@@ -1,3 +1,11 @@
+use std::io::BufRead;
+use std::os::fd::BorrowedFd;
+use std::time::{Duration, Instant};
+
+use nix::errno::Errno;
+use nix::poll::{PollFd, PollFlags, PollTimeout};
+
+use crate::editor::{self, RawMode};
 use crate::result::Result;
 
 #[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, PartialOrd, Ord)]
@@ -13,14 +21,47 @@ pub enum CommandCompleteness {
 
 pub enum LineInput {
     Line(String),
+    /// Ctrl-C on the terminal, which abandons the line being typed.
+    Interrupted,
     Eof,
 }
 
-pub fn read_input() -> Result<LineInput> {
-    let stdin = std::io::stdin();
-    let mut line = String::new();
-    let bytes_read = stdin.read_line(&mut line)?;
-    if bytes_read == 0 { Ok(LineInput::Eof) } else { Ok(LineInput::Line(line)) }
+/// Reads the next line of input, edited on the terminal when it is in raw mode.
+pub fn read_input(terminal: Option<&RawMode<'_>>) -> Result<LineInput> {
+    match terminal {
+        Some(raw_mode) => editor::read_line(raw_mode),
+        None => read_line_from(&mut std::io::stdin().lock()),
+    }
+}
+
+/// Reads a line as raw bytes, so input that isn't valid UTF-8 is converted lossily rather than
+/// failing the read and taking the shell down with it.
+fn read_line_from(reader: &mut impl BufRead) -> Result<LineInput> {
+    let mut line = vec![];
+    let bytes_read = reader.read_until(b'\n', &mut line)?;
+    if bytes_read == 0 {
+        return Ok(LineInput::Eof);
+    }
+
+    Ok(LineInput::Line(String::from_utf8_lossy(&line).into_owned()))
+}
+
+/// Waits up to `timeout` for `fd` to become readable, returning whether it did.
+pub fn wait_for_input(fd: BorrowedFd<'_>, timeout: Duration) -> Result<bool> {
+    let deadline = Instant::now() + timeout;
+
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        let poll_timeout = PollTimeout::try_from(remaining).unwrap_or(PollTimeout::MAX);
+        let mut fds = [PollFd::new(fd, PollFlags::POLLIN)];
+
+        match nix::poll::poll(&mut fds, poll_timeout) {
+            Ok(ready) => return Ok(ready > 0),
+            // a signal like SIGCHLD interrupted the wait, keep waiting for what is left
+            Err(Errno::EINTR) => continue,
+            Err(e) => return Err(std::io::Error::from(e).into()),
+        }
+    }
 }
 
 pub fn determine_command_completeness(text: &str) -> CommandCompleteness {
@@ -30,9 +71,17 @@ pub fn determine_command_completeness(text: &str) -> CommandCompleteness {
     let mut braces = 0;
     let mut brackets = 0;
 
+    let mut prev = None;
     let mut iter = text.chars().peekable();
     while let Some(ch) = iter.next() {
+        let at_word_start = prev.is_none_or(|prev: char| prev.is_whitespace() || matches!(prev, ';' | '&' | '|'));
+
         match ch {
+            // an unquoted `#` starting a word comments out the rest of the line, so quotes or
+            // brackets inside the comment must not leave the command open
+            '#' if !in_single_quote && !in_double_quote && at_word_start => {
+                while iter.next_if(|&ch| ch != '\n').is_some() {}
+            }
             '\'' if !in_double_quote => in_single_quote = !in_single_quote,
             '"' if !in_single_quote => in_double_quote = !in_double_quote,
             '(' if !in_single_quote && !in_double_quote => parens += 1,
@@ -64,6 +113,8 @@ pub fn determine_command_completeness(text: &str) -> CommandCompleteness {
             },
             _ => {}
         }
+
+        prev = Some(ch);
     }
 
     if in_single_quote {
@@ -87,6 +138,8 @@ pub fn determine_command_completeness(text: &str) -> CommandCompleteness {
 
 #[cfg(test)]
 mod tests {
+    use std::os::fd::AsFd;
+
     use super::*;
 
     #[test]
@@ -111,4 +164,48 @@ mod tests {
         let completeness = determine_command_completeness(&command);
         assert_eq!(completeness, CommandCompleteness::OpenSingleQuote);
     }
+
+    #[test]
+    fn test_read_invalid_utf8_line() {
+        let mut input = std::io::Cursor::new(b"echo caf\xe9\nls\n".to_vec());
+
+        let LineInput::Line(line) = read_line_from(&mut input).unwrap() else { panic!("expected a line") };
+        assert_eq!(line, "echo caf\u{fffd}\n");
+
+        let LineInput::Line(line) = read_line_from(&mut input).unwrap() else { panic!("expected a line") };
+        assert_eq!(line, "ls\n");
+
+        assert!(matches!(read_line_from(&mut input).unwrap(), LineInput::Eof));
+    }
+
+    #[test]
+    fn test_wait_for_input_times_out() {
+        let (read, write) = nix::unistd::pipe().unwrap();
+        let timeout = Duration::from_millis(50);
+
+        assert!(!wait_for_input(read.as_fd(), timeout).unwrap());
+
+        nix::unistd::write(&write, b"echo hi\n").unwrap();
+        assert!(wait_for_input(read.as_fd(), timeout).unwrap());
+    }
+
+    #[test]
+    fn test_command_completeness_ignores_comments() {
+        assert_eq!(
+            determine_command_completeness("echo hi # don't"),
+            CommandCompleteness::Complete
+        );
+        assert_eq!(
+            determine_command_completeness("# (\necho hi"),
+            CommandCompleteness::Complete
+        );
+        assert_eq!(
+            determine_command_completeness("echo '# it"),
+            CommandCompleteness::OpenSingleQuote
+        );
+        assert_eq!(
+            determine_command_completeness("echo a#'b"),
+            CommandCompleteness::OpenSingleQuote
+        );
+    }
 }
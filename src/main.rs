@@ -1,3 +1,6 @@
+mod args;
+mod diagnostic;
+mod editor;
 mod input;
 mod result;
 mod rush;
@@ -6,6 +9,19 @@ use crate::result::Result;
 use crate::rush::Rush;
 
 fn main() -> Result<()> {
-    Rush::new().run()?;
+    let args = match args::parse_args(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("rush: {e}");
+            std::process::exit(2);
+        }
+    };
+
+    if let Some(command) = args.command {
+        let status = Rush::new(args.command_timeout, false, args.verbose).run_command(&command);
+        std::process::exit(status);
+    }
+
+    Rush::new(args.command_timeout, true, args.verbose).run()?;
     Ok(())
 }
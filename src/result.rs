@@ -6,4 +6,6 @@ pub enum Error {
     Io(#[from] std::io::Error),
     #[error("{0}")]
     Runner(#[from] rush_runner::Error),
+    #[error("{0}")]
+    InvalidArgs(String),
 }
@@ -1,28 +1,68 @@
 use std::collections::HashMap;
-use std::io::{Write, stdout};
+use std::io::{IsTerminal, Write, stdout};
+use std::os::fd::BorrowedFd;
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
-use rush_runner::ExecCtx;
+use rush_runner::{ExecCtx, History, ShellOptions};
 
-use crate::input::{CommandCompleteness, LineInput, determine_command_completeness, read_input};
+use crate::diagnostic::render_diagnostic;
+use crate::editor::RawMode;
+use crate::input::{CommandCompleteness, LineInput, determine_command_completeness, read_input, wait_for_input};
 use crate::result::Result;
 
 pub struct Rush {
     jobs: Arc<Mutex<HashMap<u32, rush_runner::Job>>>,
     next_job_id: Arc<Mutex<u32>>,
     shell_pgid: nix::unistd::Pid,
+    shell_pid: nix::unistd::Pid,
+    shell_parent_pid: nix::unistd::Pid,
     shell_terminal: i32,
+    is_interactive: bool,
+    options: Arc<Mutex<ShellOptions>>,
+    last_background_pid: Arc<Mutex<Option<nix::unistd::Pid>>>,
+    last_status: Arc<Mutex<i32>>,
+    pipe_status: Arc<Mutex<Vec<i32>>>,
+    env: Arc<Mutex<rush_runner::Env>>,
+    current_command: Arc<Mutex<String>>,
+    command_timeout: Option<Duration>,
+    history: Arc<Mutex<History>>,
 }
 
 impl Rush {
-    pub fn new() -> Self {
-        let (shell_pgid, shell_terminal) = rush_runner::init_shell().expect("Failed to initialize shell");
+    /// A shell that is interactive when stdin is a terminal, unless `interactive` is false as it is
+    /// for `-c`, and that echoes its input from the start when `verbose`.
+    pub fn new(command_timeout: Option<Duration>, interactive: bool, verbose: bool) -> Self {
+        let (shell_pgid, shell_terminal, is_interactive) =
+            rush_runner::init_shell(interactive).expect("Failed to initialize shell");
+
+        let mut env = rush_runner::Env::from_process();
+        env.sync_pwd();
+        env.increment_shlvl();
+        // only what is typed at the prompt is history, not scripts or `-c` strings
+        let history = match is_interactive {
+            true => History::load(History::default_path(&env)),
+            false => History::default(),
+        };
+        let mut options = ShellOptions::new(is_interactive);
+        options.verbose = verbose;
 
         Self {
             jobs: Arc::new(Mutex::new(HashMap::new())),
             next_job_id: Arc::new(Mutex::new(1)),
             shell_pgid,
+            shell_pid: nix::unistd::getpid(),
+            shell_parent_pid: nix::unistd::getppid(),
             shell_terminal,
+            is_interactive,
+            options: Arc::new(Mutex::new(options)),
+            last_background_pid: Arc::new(Mutex::new(None)),
+            last_status: Arc::new(Mutex::new(0)),
+            pipe_status: Arc::new(Mutex::new(vec![])),
+            env: Arc::new(Mutex::new(env)),
+            current_command: Arc::new(Mutex::new(String::new())),
+            command_timeout,
+            history: Arc::new(Mutex::new(history)),
         }
     }
 
@@ -31,8 +71,14 @@ impl Rush {
         let mut input_buffer = String::new();
         let mut completeness = CommandCompleteness::Complete;
 
+        let stdin_fd = unsafe { BorrowedFd::borrow_raw(self.shell_terminal) };
+
         loop {
-            input_buffer.clear();
+            // raw mode goes on before the prompt, so keys typed as soon as it shows are edited too
+            let raw_mode = match stdin_fd.is_terminal() {
+                true => Some(RawMode::enable(stdin_fd)?),
+                false => None,
+            };
 
             match completeness {
                 CommandCompleteness::Complete => write!(stdout, "$ ")?,
@@ -45,10 +91,29 @@ impl Rush {
             }
 
             stdout.flush()?;
-            let LineInput::Line(line) = read_input()? else {
-                writeln!(stdout)?;
+
+            if completeness == CommandCompleteness::Complete
+                && let Some(timeout) = self.idle_timeout()
+                && !wait_for_input(stdin_fd, timeout)?
+            {
+                writeln!(stdout, "\nrush: timed out waiting for input: auto-logout")?;
                 break;
+            }
+
+            let line = match read_input(raw_mode.as_ref())? {
+                LineInput::Line(line) => line,
+                // Ctrl-C gives up on the whole command, the lines it was continuing included
+                LineInput::Interrupted => {
+                    input_buffer.clear();
+                    completeness = CommandCompleteness::Complete;
+                    continue;
+                }
+                LineInput::Eof => {
+                    writeln!(stdout)?;
+                    break;
+                }
             };
+            drop(raw_mode);
 
             input_buffer.push_str(&line);
             completeness = determine_command_completeness(&input_buffer);
@@ -56,25 +121,81 @@ impl Rush {
                 continue;
             }
 
-            let tokens = rush_lexer::Lexer::new(&input_buffer).lex();
-            let Ok(commands) = rush_parser::Parser::new().parse(tokens) else {
-                writeln!(stdout, "Parse error")?;
-                continue;
-            };
+            if self.is_interactive {
+                self.history.lock().unwrap().push(&input_buffer);
+            }
+            self.execute_source(&input_buffer);
+            input_buffer.clear();
+        }
 
-            rush_runner::update_job_statuses(self.jobs.clone());
+        Ok(())
+    }
 
-            let mut ctx = ExecCtx {
-                source: &input_buffer,
-                jobs: self.jobs.clone(),
-                next_job_id: self.next_job_id.clone(),
-                shell_pgid: self.shell_pgid,
-                shell_terminal: self.shell_terminal,
-            };
+    /// Runs `source` as `rush -c` does, returning the status of its last command.
+    pub fn run_command(&mut self, source: &str) -> i32 {
+        self.execute_source(source);
+        *self.last_status.lock().unwrap()
+    }
 
-            rush_runner::execute(&mut ctx, commands)?;
+    fn execute_source(&mut self, source: &str) {
+        // `-v` echoes the input as it was read, before anything is parsed out of it
+        if self.options.lock().unwrap().verbose {
+            match source.ends_with('\n') {
+                true => eprint!("{source}"),
+                false => eprintln!("{source}"),
+            }
         }
 
-        Ok(())
+        // blank lines and comments have nothing to run
+        let tokens = rush_lexer::Lexer::new(source).lex();
+        if tokens.peek() == rush_lexer::TokenKind::Eof {
+            return;
+        }
+
+        let commands = match rush_parser::Parser::new().parse(tokens) {
+            Ok(commands) => commands,
+            Err(e) => {
+                let color = std::io::stderr().is_terminal();
+                eprint!("{}", render_diagnostic(source, &e, color));
+                *self.last_status.lock().unwrap() = 2;
+                return;
+            }
+        };
+
+        rush_runner::update_job_statuses(self.jobs.clone());
+
+        let mut ctx = ExecCtx {
+            source,
+            jobs: self.jobs.clone(),
+            next_job_id: self.next_job_id.clone(),
+            shell_pgid: self.shell_pgid,
+            shell_pid: self.shell_pid,
+            shell_parent_pid: self.shell_parent_pid,
+            shell_terminal: self.shell_terminal,
+            options: self.options.clone(),
+            last_background_pid: self.last_background_pid.clone(),
+            last_status: self.last_status.clone(),
+            pipe_status: self.pipe_status.clone(),
+            env: self.env.clone(),
+            current_command: self.current_command.clone(),
+            history: self.history.clone(),
+            command_timeout: self.command_timeout,
+        };
+
+        // a command that fails to run is reported, the shell itself carries on
+        if let Err(e) = rush_runner::execute(&mut ctx, commands) {
+            eprintln!("rush: {e}");
+        }
+    }
+
+    /// `TMOUT` seconds an interactive shell waits at the prompt before exiting, read from the
+    /// shell's variables each time so setting it at the prompt takes effect.
+    fn idle_timeout(&self) -> Option<Duration> {
+        if !self.is_interactive {
+            return None;
+        }
+
+        let seconds = self.env.lock().unwrap().get("TMOUT")?.parse::<u64>().ok()?;
+        (seconds > 0).then(|| Duration::from_secs(seconds))
     }
 }
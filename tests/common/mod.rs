@@ -0,0 +1,121 @@
+use std::fs::File;
+use std::io::{Read, Write};
+use std::os::fd::AsFd;
+use std::os::unix::process::CommandExt;
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::time::{Duration, Instant};
+
+use nix::poll::{PollFd, PollFlags, PollTimeout, poll};
+use nix::pty::openpty;
+
+/// How long `read_until` waits for the shell before giving up.
+pub const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A rush process whose stdin, stdout and stderr are the slave end of a pseudo-terminal, so it
+/// runs interactively with the pty as its controlling terminal.
+pub struct PtyShell {
+    master: File,
+    child: Child,
+    output: String,
+}
+
+impl PtyShell {
+    pub fn spawn() -> Self {
+        let pty = openpty(None, None).expect("failed to open a pty");
+
+        let mut command = Command::new(env!("CARGO_BIN_EXE_rush"));
+        command
+            .stdin(Stdio::from(pty.slave.try_clone().unwrap()))
+            .stdout(Stdio::from(pty.slave.try_clone().unwrap()))
+            .stderr(Stdio::from(pty.slave));
+
+        // a new session without a terminal, then claim the pty as the controlling terminal
+        unsafe {
+            command.pre_exec(|| {
+                nix::unistd::setsid()?;
+                if nix::libc::ioctl(nix::libc::STDIN_FILENO, nix::libc::TIOCSCTTY as _, 0) == -1 {
+                    return Err(std::io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+
+        let child = command.spawn().expect("failed to spawn rush");
+
+        Self {
+            master: File::from(pty.master),
+            child,
+            output: String::new(),
+        }
+    }
+
+    /// Types `input` into the terminal, as if the user did.
+    pub fn send(&mut self, input: &str) {
+        self.master
+            .write_all(input.as_bytes())
+            .expect("failed to write to the pty");
+    }
+
+    /// Reads output until `needle` shows up and returns everything read up to and including it,
+    /// whatever comes after is kept for the next read.
+    pub fn read_until(&mut self, needle: &str) -> String {
+        let deadline = Instant::now() + READ_TIMEOUT;
+
+        loop {
+            if let Some(index) = self.output.find(needle) {
+                let rest = self.output.split_off(index + needle.len());
+                return std::mem::replace(&mut self.output, rest);
+            }
+
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            assert!(
+                !remaining.is_zero(),
+                "timed out waiting for {needle:?}, got {:?}",
+                self.output
+            );
+
+            let timeout = PollTimeout::try_from(remaining).unwrap_or(PollTimeout::MAX);
+            let mut fds = [PollFd::new(self.master.as_fd(), PollFlags::POLLIN)];
+            match poll(&mut fds, timeout) {
+                Ok(0) | Err(nix::errno::Errno::EINTR) => continue,
+                Ok(_) => {}
+                Err(e) => panic!("failed to poll the pty: {e}"),
+            }
+
+            let mut buffer = [0; 1024];
+            match self.master.read(&mut buffer) {
+                // linux reports EIO once the slave end is closed
+                Ok(0) | Err(_) => panic!(
+                    "shell closed the pty while waiting for {needle:?}, got {:?}",
+                    self.output
+                ),
+                Ok(n) => self.output.push_str(&String::from_utf8_lossy(&buffer[..n])),
+            }
+        }
+    }
+
+    /// Waits for the next primary prompt.
+    pub fn wait_for_prompt(&mut self) -> String {
+        self.read_until("$ ")
+    }
+
+    /// Waits for the shell to exit on its own.
+    pub fn wait(&mut self) -> ExitStatus {
+        let deadline = Instant::now() + READ_TIMEOUT;
+
+        loop {
+            if let Some(status) = self.child.try_wait().expect("failed to wait for rush") {
+                return status;
+            }
+            assert!(Instant::now() < deadline, "timed out waiting for the shell to exit");
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+}
+
+impl Drop for PtyShell {
+    fn drop(&mut self) {
+        _ = self.child.kill();
+        _ = self.child.wait();
+    }
+}
@@ -0,0 +1,73 @@
+mod common;
+
+use common::PtyShell;
+
+#[test]
+fn test_pty_runs_a_command() {
+    let mut shell = PtyShell::spawn();
+    shell.wait_for_prompt();
+
+    shell.send("echo hello\n");
+    let output = shell.wait_for_prompt();
+
+    // the terminal echoes the input back before the command's own output
+    assert_eq!(output, "echo hello\r\nhello\r\n$ ");
+}
+
+#[test]
+fn test_pty_exits_on_eof() {
+    let mut shell = PtyShell::spawn();
+    shell.wait_for_prompt();
+
+    // ^D on an empty line is end of input on a terminal
+    shell.send("\x04");
+    let status = shell.wait();
+    assert!(status.success());
+}
+
+#[test]
+fn test_pty_edits_the_line() {
+    let mut shell = PtyShell::spawn();
+    shell.wait_for_prompt();
+
+    // left twice, insert, end, then type and erase a character
+    shell.send("echo ab\x1b[D\x1b[Dx\x1b[Fy\x7f\n");
+    let output = shell.wait_for_prompt();
+    assert!(output.ends_with("\r\nxab\r\n$ "), "{output:?}");
+
+    // ^C drops the line and the next one is read as usual
+    shell.send("echo lost\x03");
+    assert_eq!(shell.wait_for_prompt(), "echo lost^C\r\n$ ");
+    shell.send("echo kept\n");
+    assert_eq!(shell.wait_for_prompt(), "echo kept\r\nkept\r\n$ ");
+}
+
+#[test]
+fn test_pty_tmout_set_at_the_prompt() {
+    let mut shell = PtyShell::spawn();
+    shell.wait_for_prompt();
+
+    shell.send("export TMOUT=1\n");
+    shell.wait_for_prompt();
+    shell.read_until("auto-logout");
+    assert!(shell.wait().success());
+}
+
+#[test]
+fn test_pty_continuation_prompt() {
+    let mut shell = PtyShell::spawn();
+    shell.wait_for_prompt();
+
+    shell.send("echo \"a\n");
+    shell.read_until("(dquote)> ");
+    shell.send("b\"\n");
+    assert_eq!(shell.wait_for_prompt(), "b\"\r\na\r\nb\r\n$ ");
+
+    // ^C at a continuation prompt abandons the whole command
+    shell.send("echo \"lost\n");
+    shell.read_until("(dquote)> ");
+    shell.send("still lost\x03");
+    assert_eq!(shell.wait_for_prompt(), "still lost^C\r\n$ ");
+    shell.send("echo kept\n");
+    assert_eq!(shell.wait_for_prompt(), "echo kept\r\nkept\r\n$ ");
+}
@@ -0,0 +1,68 @@
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// Runs `script` through a non-interactive rush reading it from stdin, returning its stderr.
+fn run_script(script: &str) -> String {
+    run_script_with(script, &[], &[])
+}
+
+fn run_script_with(script: &str, args: &[&str], env: &[(&str, &str)]) -> String {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_rush"))
+        .args(args)
+        .envs(env.iter().copied())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    child.stdin.take().unwrap().write_all(script.as_bytes()).unwrap();
+    let output = child.wait_with_output().unwrap();
+    String::from_utf8(output.stderr).unwrap()
+}
+
+#[test]
+fn test_verbose_echoes_source_lines() {
+    let stderr = run_script("set -v\necho $?   # status\n");
+    assert_eq!(stderr, "echo $?   # status\n");
+}
+
+#[test]
+fn test_verbose_flag_echoes_every_input() {
+    let stderr = run_script_with("true\necho $?\n", &["-v"], &[]);
+    assert_eq!(stderr, "true\necho $?\n");
+
+    let stderr = run_script_with("", &["-v", "-c", "echo $?   # status"], &[]);
+    assert_eq!(stderr, "echo $?   # status\n");
+}
+
+#[test]
+fn test_xtrace_echoes_expanded_commands() {
+    let stderr = run_script("set -x\necho $?   # status\n");
+    assert_eq!(stderr, "+ echo 0\n");
+}
+
+#[test]
+fn test_shlvl_counts_nested_shells() {
+    let stderr = run_script_with("echo $SHLVL 1>&2\nprintenv SHLVL 1>&2\n", &[], &[("SHLVL", "2")]);
+    assert_eq!(stderr, "3\n3\n");
+}
+
+/// Runs `rush -c command`, returning its exit status and stdout.
+fn run_command(command: &str) -> (i32, String) {
+    let output = Command::new(env!("CARGO_BIN_EXE_rush"))
+        .args(["-c", command])
+        .stdin(Stdio::null())
+        .output()
+        .unwrap();
+    (output.status.code().unwrap(), String::from_utf8(output.stdout).unwrap())
+}
+
+#[test]
+fn test_command_string() {
+    assert_eq!(run_command("true"), (0, String::new()));
+    assert_eq!(run_command("false"), (1, String::new()));
+    assert_eq!(run_command("echo hi; echo bye | cat && false"), (1, "hi\nbye\n".into()));
+    assert_eq!(run_command("false; sleep 0 &").0, 0);
+    assert_eq!(run_command("echo |").0, 2);
+}